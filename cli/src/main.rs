@@ -15,16 +15,25 @@ struct Cli {
 	instance: String,
 }
 
+const DEFAULT_BOARD_SIZE: usize = 3;
+// proposals made from the CLI stay open for an hour
+const PROPOSAL_LIFETIME_MS: u64 = 60 * 60 * 1000;
+
 static COMMANDS: &[(&str, &str)] = &[
     ("help",             "Displays this the help page"),
     ("join_game",        "Set the game to make moves against, usage: join_game <game_address>"),
-    ("new_game",         "Create a new game to play with an opponent, usage: new_game <opponent_address>"),
+    ("new_game",         "Create a new game to play with an opponent, usage: new_game <opponent_address> [width] [height]"),
+    ("my_games",         "List the games you are playing along with their status"),
+    ("get_invitations",  "List the games other players have invited you to that you haven't answered yet"),
+    ("accept_invitation", "Agree to play a game you were invited to. Usage: accept_invitation <invitation_hash>"),
+    ("decline_invitation", "Turn down a game you were invited to. Usage: decline_invitation <invitation_hash>"),
     ("moves",            "Display the set of moves this game supports"),
     ("make_move",        "Make a move in this game, usage: make_move <move_json>"),
+    ("reveal_coin_flip", "Reveal your secret to settle who goes first in this game after your opponent has seeded the coin flip"),
  
-    ("create_proposal",  "Publicly publish that you are looking for someone to play with. Usage: post_propoal <message>"),
-    ("accept_proposal",   "Accept a propsal. This will start a new game. Usage: accept_proposal <proposal_hash>"),
-    ("get_proposals",    "Get all of the public proposals that are current"),
+    ("create_proposal",  "Publicly publish that you are looking for someone to play with on a standard board. Usage: post_propoal <message>"),
+    ("accept_proposal",   "Accept a propsal. This will start a new game with the proposal's settings. Usage: accept_proposal <proposal_hash>"),
+    ("get_proposals",    "Get all of the public proposals that haven't expired or been accepted yet"),
     ("check_responses",  "Given a proposal hash find the responses. Usage: check_responses <proposal_hash>"),
     ("remove_proposal",  "Remove a proposal that you authored given its hash. Usage: remove_proposal <proposal_hash>"),    
  
@@ -40,13 +49,18 @@ fn main() -> io::Result<()> {
     let make_move = holochain_call_generator(cli.url.clone(), cli.instance.clone(), "main".into(), "make_move".into());
     let create_game = holochain_call_generator(cli.url.clone(), cli.instance.clone(), "main".into(), "create_game".into());
     let render_game = holochain_call_generator(cli.url.clone(), cli.instance.clone(), "main".into(), "render_state".into());
+    let get_my_games = holochain_call_generator(cli.url.clone(), cli.instance.clone(), "main".into(), "get_my_games".into());
+    let reveal_coin_flip = holochain_call_generator(cli.url.clone(), cli.instance.clone(), "main".into(), "reveal_coin_flip".into());
+    let get_invitations = holochain_call_generator(cli.url.clone(), cli.instance.clone(), "main".into(), "get_invitations".into());
+    let accept_invitation = holochain_call_generator(cli.url.clone(), cli.instance.clone(), "main".into(), "accept_invitation".into());
+    let decline_invitation = holochain_call_generator(cli.url.clone(), cli.instance.clone(), "main".into(), "decline_invitation".into());
 
     // matchmaking funcs
     let create_proposal = holochain_call_generator(cli.url.clone(), cli.instance.clone(), "main".into(), "create_proposal".into());
     let get_proposals = holochain_call_generator(cli.url.clone(), cli.instance.clone(), "main".into(), "get_proposals".into());
     let accept_proposal = holochain_call_generator(cli.url.clone(), cli.instance.clone(), "main".into(), "accept_proposal".into());
     let check_responses = holochain_call_generator(cli.url.clone(), cli.instance.clone(), "main".into(), "check_responses".into());
-    let _remove_proposal = holochain_call_generator(cli.url.clone(), cli.instance.clone(), "main".into(), "remove_proposal".into());
+    let remove_proposal = holochain_call_generator(cli.url.clone(), cli.instance.clone(), "main".into(), "remove_proposal".into());
    
 
     let interface = Interface::new("Holochain generic game")?;
@@ -108,18 +122,54 @@ fn main() -> io::Result<()> {
             	}
             }
             "new_game" => {
-            	if is_agent_addr(args) {
-            		let result = create_game(json!({
-            			"opponent": args,
-            			"timestamp": current_timestamp()
-            		}));
-                    result.map(|result| {
+                let (opponent, size_args) = split_first_word(args);
+            	if is_agent_addr(opponent) {
+                    parse_board_size(size_args).and_then(|(width, height)| {
+                        create_game(json!({
+                            "opponent": opponent,
+                            "timestamp": current_timestamp(),
+                            "settings": {
+                                "width": width,
+                                "height": height
+                            }
+                        }))
+                    }).map(|result| {
                         current_game = result.as_str().map(|s| s.to_string());
                     })
             	} else {
             		Err("argument must be valid agent address of an opponent.".into())
             	}
             }
+            "my_games" => {
+                get_my_games(json!({})).map(|result| {
+                    println!("Your games: \n");
+                    result.as_array().unwrap().iter().for_each(|game| {
+                        println!("[{}] : {{ Opponent: {}, Invitation: {}, Status: {}, Result: {}, Your turn: {} }}",
+                            game["address"].as_str().unwrap(), game["opponent"], game["invitation"], game["status"], game["result"], game["my_turn"]);
+                    });
+                    println!("use \"join_game\" with any of the listed addresses to play: \n");
+                })
+            },
+            "get_invitations" => {
+                get_invitations(json!({})).map(|result| {
+                    println!("Your invitations: \n");
+                    result.as_array().unwrap().iter().for_each(|invitation| {
+                        println!("[{}] : {{ Game: {}, From: {} }}",
+                            invitation["address"].as_str().unwrap(), invitation["entry"]["game"], invitation["entry"]["inviter"]);
+                    });
+                    println!("use \"accept_invitation\" or \"decline_invitation\" with any of the listed addresses to answer: \n");
+                })
+            },
+            "accept_invitation" => {
+                accept_invitation(json!({"invitation_addr": args})).map(|_| {
+                    println!("Invitation accepted. Use \"my_games\" to find the game and \"join_game\" to play it.\n");
+                })
+            },
+            "decline_invitation" => {
+                decline_invitation(json!({"invitation_addr": args})).map(|_| {
+                    println!("Invitation declined\n");
+                })
+            },
             "moves" => {
             	valid_moves(json!({})).map(|result| {
 	            	println!("The valid moves are:");
@@ -153,23 +203,48 @@ fn main() -> io::Result<()> {
             		Err("No game set to make moves on. use the \"join_game\" command.".into())
             	}
             },
+            "reveal_coin_flip" => {
+                if let Some(current_game) = current_game.clone() {
+                    reveal_coin_flip(json!({
+                        "game_address": current_game,
+                        "timestamp": current_timestamp()
+                    })).map(|_| {
+                        println!("Coin flip revealed");
+                    })
+                }
+                else {
+                    Err("No game set to make moves on. use the \"join_game\" command.".into())
+                }
+            },
             "create_proposal" => {
                 println!("creating proposal with message {:?}", args);
-                let result = create_proposal(json!({"message": args}));
+                let result = create_proposal(json!({
+                    "message": args,
+                    "settings": {
+                        "width": DEFAULT_BOARD_SIZE,
+                        "height": DEFAULT_BOARD_SIZE
+                    },
+                    "expires_at": current_timestamp() + PROPOSAL_LIFETIME_MS
+                }));
                 println!("Create result: {:?}", result);
                 Ok(())
             },
             "get_proposals" => {
-                let result = get_proposals(json!({})).unwrap();
+                let result = get_proposals(json!({"now": current_timestamp()})).unwrap();
                 println!("Current game proposals: \n");
                 result.as_array().unwrap().iter().for_each(|r| {
-                    println!("[{}] : {{ Agent: {}, Message: {} }}", r["address"].as_str().unwrap(), r["entry"]["agent"], r["entry"]["message"]);
+                    let settings = &r["entry"]["settings"];
+                    println!("[{}] : {{ Agent: {}, Message: {}, Board: {}x{}, Variant: {}, Rated: {} }}", r["address"].as_str().unwrap(), r["entry"]["agent"], r["entry"]["message"],
+                        settings["width"], settings["height"], settings["variant"], settings["rated"]);
                 });
                 println!("\n");
                 Ok(())
             },
             "accept_proposal" => {
-                accept_proposal(json!({"proposal_addr": args, "created_at": current_timestamp()})).map(|game_addr| {
+                accept_proposal(json!({
+                    "proposal_addr": args,
+                    "created_at": current_timestamp()
+                })).map(|game_addr| {
                     println!("Proposal accepted. Game created with address: {}", game_addr);
                     current_game = Some(game_addr.as_str().unwrap().into());
                 })
@@ -178,17 +253,16 @@ fn main() -> io::Result<()> {
                 let result = check_responses(json!({"proposal_addr": args})).unwrap();
                 println!("Proposal has the following responses: \n");
                 result.as_array().unwrap().iter().for_each(|response| {
-                    println!("[{}] : Agent: {}", response["address"], response["entry"]["player_1"]);
+                    println!("[{}] : {{ Agent: {}, Status: {} }}", response["address"], response["entry"]["player_1"], response["status"]);
                 });
-                println!("use \"join_game\" with any of the listed addresses to join: \n");
+                println!("If this is your proposal, use \"get_invitations\" and \"accept_invitation\" to pick one. The others are declined.");
+                println!("use \"join_game\" with the accepted address to play: \n");
                 Ok(())            
             },
             "remove_proposal" => {
-                println!("NOT IMPLEMENTED - this has been disabled until deletion but is fixed.");
-                Ok(())
-                // remove_proposal(json!({"proposal_addr": args})).map(|_| {
-                //     println!("Proposal successfully marked as deleted\n");
-                // })
+                remove_proposal(json!({"proposal_addr": args})).map(|_| {
+                    println!("Proposal successfully marked as deleted\n");
+                })
             },
             "exit" => {
             	if let Some(current_game) = current_game.clone() {
@@ -293,8 +367,22 @@ fn is_agent_addr(s: &str) -> bool {
 	s.starts_with("Hc") && s.len() == 63
 }
 
-fn current_timestamp() -> u32 {
-	SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as u32
+/// Board size is the number of grid points along each axis given as "[width] [height]".
+/// A single number gives a square board and no numbers gives the classic 3x3 dots.
+fn parse_board_size(s: &str) -> Result<(usize, usize), String> {
+    let parse = |n: &str| n.parse::<usize>().map_err(|_| format!("board size must be a number, got {:?}", n));
+    let (width, height) = split_first_word(s);
+    match (width.is_empty(), height.is_empty()) {
+        (true, _) => Ok((DEFAULT_BOARD_SIZE, DEFAULT_BOARD_SIZE)),
+        (false, true) => parse(width).map(|size| (size, size)),
+        (false, false) => Ok((parse(width)?, parse(height)?)),
+    }
+}
+
+/// Milliseconds since the unix epoch, which is what games and moves are timestamped with
+fn current_timestamp() -> u64 {
+	let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+	since_epoch.as_secs() * 1000 + u64::from(since_epoch.subsec_millis())
 }
 
 /*=====  End of Helpers  ======*/
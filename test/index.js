@@ -25,27 +25,53 @@ const diorama = new Diorama({
 
 // <<DEVCAMP>> Your tests here
 
-
-//Scenarios keep a series of test steps separate from the overall test - clean slate inbetween
-diorama.registerScenario("Can create a new game and make moves", async(s, t, { alice, bob}) => {
-
-//Moves start at timestamp=10
-move_timestamp = 10
+//Games only start once player 2 has accepted the invitation that creating the game sent them
+async function acceptInvitation (player, game_address) {
+  const invitations = await player.callSync('main', 'get_invitations', {})
+  const invitation = invitations.Ok.find(response => response.entry.game == game_address)
+  return player.callSync('main', 'accept_invitation', { invitation_addr: invitation.address })
+}
 
 function sleep(ms) {
   return new Promise(resolve => setTimeout(resolve, ms));
-} 
-
-async function moveHelper (game, player, x, y, direction, valid) {
-  console.log(`========Player ${player.id} moves (x=${x},y=${y},${direction}) and expects ${valid}.`);
+}
 
-  move_result = await player.callSync('main', 'make_move', {
+//Make any type of move in a game and return the result so the caller can check it. Moves are
+//only valid if their timestamp is close to when they were committed, so it defaults to now
+async function move (game, player, move_type, timestamp = Date.now()) {
+  return player.callSync('main', 'make_move', {
     new_move: {
       game: game,
-      move_type: { Place: {x:x, y:y, direction:direction} },
-      timestamp: move_timestamp,
+      move_type: move_type,
+      timestamp: timestamp,
     }
   })
+}
+
+//Create a game on a 3x3 board unless the settings say otherwise and check its address came back.
+//The opponent accepts the invitation straight away unless accept is false.
+async function createGame (t, creator, opponent, settings = {}, accept = true) {
+  //If you *.call instead of *.callSync, then you will not be waiting for the sychronization to complete
+  const create_game_result = await creator.callSync('main', 'create_game', {
+    opponent: opponent.agentId,
+    timestamp: Date.now(),
+    settings: Object.assign({ width: 3, height: 3 }, settings),
+  })
+  // Check the ok respose is an address
+  t.equal(create_game_result.Ok.length, 46)
+  if (accept) {
+    await acceptInvitation(opponent, create_game_result.Ok)
+  }
+  return create_game_result.Ok
+}
+
+//Scenarios keep a series of test steps separate from the overall test - clean slate inbetween
+diorama.registerScenario("Can create a new game and make moves", async(s, t, { alice, bob}) => {
+
+async function moveHelper (game, player, x, y, direction, valid) {
+  console.log(`========Player ${player.id} moves (x=${x},y=${y},${direction}) and expects ${valid}.`);
+
+  const move_result = await move(game, player, { Place: {x:x, y:y, direction:direction} })
   console.log(move_result)
   if (valid) {
     // make sure the move was made without error
@@ -64,73 +90,503 @@ async function moveHelper (game, player, x, y, direction, valid) {
   })  
   console.log(render_state.Ok)
 
-  move_timestamp++
-
   await sleep(6000); //Sleep 3 seconds to see if that gets around the Hachiko timeout
   
 }
 
-  //If you *.call instead of *.callSync, then you will not be waiting for the sychronization to complete
-  const create_game_result = await alice.callSync('main', 'create_game', {
-    opponent: bob.agentId,
-    timestamp: 0,
-  })
+  const game = await createGame(t, alice, bob, {}, false)
   console.log('Player 1 is Alice; Player 2 is Bob')
 
-  //Might want to stringify this b/c objects embedded in the result will just show {object}
-  console.log(create_game_result)
-  // Check the ok respose is an address
-  t.equal(create_game_result.Ok.length, 46)
-
   game_state = await alice.callSync('main', 'get_state',{
-    game_address: create_game_result.Ok
+    game_address: game
   })
   console.log(JSON.stringify(game_state))
   render_state = await alice.callSync('main', 'render_state',{
-    game_address: create_game_result.Ok
+    game_address: game
   })  
   console.log(render_state.Ok)
 
+  // the game shows up in bob's list of games, but it isn't his turn until he accepts it
+  const bobs_games = await bob.callSync('main', 'get_my_games', {})
+  console.log(JSON.stringify(bobs_games))
+  t.equal(bobs_games.Ok.length, 1)
+  t.equal(bobs_games.Ok[0].address, game)
+  t.equal(bobs_games.Ok[0].opponent, alice.agentId)
+  t.equal(bobs_games.Ok[0].invitation, "Pending")
+  t.equal(bobs_games.Ok[0].my_turn, false)
+
+  // bob can't move until he accepts alice's invitation
+  await moveHelper(game, bob, 0, 0, "Up", false)
+  const bobs_invitations = await bob.callSync('main', 'get_invitations', {})
+  t.equal(bobs_invitations.Ok.length, 1)
+  t.equal(bobs_invitations.Ok[0].entry.inviter, alice.agentId)
+  // only bob can answer his invitation
+  const alice_accept = await alice.callSync('main', 'accept_invitation', { invitation_addr: bobs_invitations.Ok[0].address })
+  t.equal(alice_accept.Ok, undefined)
+  t.equal((await acceptInvitation(bob, game)).Err, undefined)
+  // answered invitations aren't listed any more
+  t.equal((await bob.callSync('main', 'get_invitations', {})).Ok.length, 0)
+  const accepted_games = await bob.callSync('main', 'get_my_games', {})
+  t.equal(accepted_games.Ok[0].invitation, "Accepted")
+  t.equal(accepted_games.Ok[0].my_turn, true)
+
   // alice try to go out of turn
-  await moveHelper(create_game_result.Ok, alice, 2, 1, "Up", false)
+  await moveHelper(game, alice, 2, 1, "Up", false)
 
   // bob try to make a series of out of range moves
-  await moveHelper(create_game_result.Ok, bob, 0, 2, "Up", false)
-  await moveHelper(create_game_result.Ok, bob, 2, 0, "Right", false)
-  await moveHelper(create_game_result.Ok, bob, 0, -1, "Up", false)  
-  await moveHelper(create_game_result.Ok, bob, -1, 0, "Right", false)  
+  await moveHelper(game, bob, 0, 2, "Up", false)
+  await moveHelper(game, bob, 2, 0, "Right", false)
+  await moveHelper(game, bob, 0, -1, "Up", false)  
+  await moveHelper(game, bob, -1, 0, "Right", false)  
 
   // bob must make the first valid move
-  await moveHelper(create_game_result.Ok, bob, 0, 0, "Up", true)
+  await moveHelper(game, bob, 0, 0, "Up", true)
   
   // alice fail to make a move in the same location
-  await moveHelper(create_game_result.Ok, alice, 0, 0, "Up", false)
+  await moveHelper(game, alice, 0, 0, "Up", false)
 
   // alice make a valid move
-  await moveHelper(create_game_result.Ok, alice, 2, 1, "Up", true)
+  await moveHelper(game, alice, 2, 1, "Up", true)
   
   // bob make a 2nd valid move
-  await moveHelper(create_game_result.Ok, bob, 0, 0, "Right", true)
+  await moveHelper(game, bob, 0, 0, "Right", true)
 
    // alice make a 2nd valid move
-   await moveHelper(create_game_result.Ok, alice, 1, 0, "Up", true)
+   await moveHelper(game, alice, 1, 0, "Up", true)
 
   // bob complete a box
-  await moveHelper(create_game_result.Ok, bob, 0, 1, "Right", true)
+  await moveHelper(game, bob, 0, 1, "Right", true)
 
   // alice try to move when it is not her turn b/c of box completion
-  await moveHelper(create_game_result.Ok, alice, 1, 1, "Right", false)
+  await moveHelper(game, alice, 1, 1, "Right", false)
 
   // bob make the same move since it is his turn
-  await moveHelper(create_game_result.Ok, bob, 1, 1, "Right", true)
+  await moveHelper(game, bob, 1, 1, "Right", true)
 
   // alice and bob alternate to complete the game
-  await moveHelper(create_game_result.Ok, alice, 0, 1, "Up", true)
-  await moveHelper(create_game_result.Ok, bob, 1, 0, "Right", true)
-  await moveHelper(create_game_result.Ok, alice, 2, 0, "Up", true)
-  await moveHelper(create_game_result.Ok, alice, 1, 1, "Up", true)
-  await moveHelper(create_game_result.Ok, bob, 0, 2, "Right", true)
-  await moveHelper(create_game_result.Ok, bob, 1, 2, "Right", true)
+  await moveHelper(game, alice, 0, 1, "Up", true)
+  await moveHelper(game, bob, 1, 0, "Right", true)
+  await moveHelper(game, alice, 2, 0, "Up", true)
+  await moveHelper(game, alice, 1, 1, "Up", true)
+  await moveHelper(game, bob, 0, 2, "Right", true)
+  await moveHelper(game, bob, 1, 2, "Right", true)
+
+  // every box is complete so nobody can move any more
+  await moveHelper(game, alice, 0, 0, "Up", false)
+})
+
+diorama.registerScenario("Lines can be placed up to the edges of a rectangular board", async(s, t, { alice, bob}) => {
+
+  // 4 grid points wide and 3 high, so 3 x 2 boxes
+  const game = await createGame(t, alice, bob, { width: 4, height: 3 })
+
+  // the four sides of the top right box, using the largest x and y each direction allows
+  t.equal((await move(game, bob, { Place: {x:2, y:2, direction:"Right"} })).Err, undefined)
+  t.equal((await move(game, alice, { Place: {x:3, y:1, direction:"Up"} })).Err, undefined)
+  t.equal((await move(game, bob, { Place: {x:2, y:1, direction:"Right"} })).Err, undefined)
+  t.equal((await move(game, alice, { Place: {x:2, y:1, direction:"Up"} })).Err, undefined)
+
+  // one step further off the board in each direction is rejected
+  t.equal((await move(game, alice, { Place: {x:3, y:0, direction:"Right"} })).Ok, undefined)
+  t.equal((await move(game, alice, { Place: {x:0, y:2, direction:"Up"} })).Ok, undefined)
+
+  // alice completed the box so she goes again
+  t.equal((await move(game, alice, { Place: {x:0, y:0, direction:"Up"} })).Err, undefined)
+
+  const game_state = await alice.callSync('main', 'get_state', {
+    game_address: game
+  })
+  console.log(JSON.stringify(game_state))
+  t.equal(game_state.Ok.lines.length, 5)
+  t.equal(game_state.Ok.player1_boxes, 1)
+  t.equal(game_state.Ok.player2_boxes, 0)
+  t.deepEqual(game_state.Ok.box_owners, [[0, 0], [0, 0], [0, 1]])
+  t.equal(game_state.Ok.next_player, 2)
+})
+
+diorama.registerScenario("Either player can resign", async(s, t, { alice, bob}) => {
+
+  const game = await createGame(t, alice, bob)
+
+  // bob makes the first move
+  const first_move_at = Date.now()
+  t.equal((await move(game, bob, { Place: {x:0, y:0, direction:"Up"} }, first_move_at)).Err, undefined)
+
+  // alice can't make a move timestamped before bob's move
+  t.equal((await move(game, alice, { Place: {x:1, y:0, direction:"Up"} }, first_move_at - 1000)).Ok, undefined)
+
+  // alice resigns even though it is not her turn to place a line
+  t.equal((await move(game, alice, "Resign")).Err, undefined)
+
+  const game_state = await bob.callSync('main', 'get_state', {
+    game_address: game
+  })
+  console.log(JSON.stringify(game_state))
+  t.equal(game_state.Ok.status, "Surrendered")
+  t.equal(game_state.Ok.resigned_player, 1)
+  t.equal(game_state.Ok.result, "Player2Wins")
+
+  // no more moves can be made once the game is over
+  t.equal((await move(game, bob, { Place: {x:1, y:0, direction:"Up"} })).Ok, undefined)
+})
+
+diorama.registerScenario("Can claim a win when the opponent runs out of time", async(s, t, { alice, bob}) => {
+
+  // each player gets ten seconds on their clock
+  const game = await createGame(t, alice, bob, { time_control: { initial_ms: 10000, increment_ms: 0 } })
+
+  t.equal((await move(game, bob, { Place: {x:0, y:0, direction:"Up"} })).Err, undefined)
+
+  // alice still has time so bob can't claim yet
+  t.equal((await move(game, bob, "ClaimTimeout")).Ok, undefined)
+  // and he can't get around that by dating his claim in the future
+  t.equal((await move(game, bob, "ClaimTimeout", Date.now() + 60000)).Ok, undefined)
+
+  // alice's clock has run out
+  await sleep(10000)
+  t.equal((await move(game, bob, "ClaimTimeout")).Err, undefined)
+
+  const game_state = await alice.callSync('main', 'get_state', {
+    game_address: game
+  })
+  console.log(JSON.stringify(game_state))
+  t.equal(game_state.Ok.status, "TimedOut")
+  t.equal(game_state.Ok.result, "Player2Wins")
+  t.equal(game_state.Ok.player1_time_ms, 0)
+  t.ok(game_state.Ok.player2_time_ms > 0 && game_state.Ok.player2_time_ms < 10000)
+})
+
+diorama.registerScenario("Clocks start when the invitation is accepted", async(s, t, { alice, bob}) => {
+
+  const game = await createGame(t, alice, bob, { time_control: { initial_ms: 10000, increment_ms: 0 }, abandon_after_ms: 10000 }, false)
+
+  // bob takes longer to accept than he has on his clock
+  await sleep(10000)
+  await acceptInvitation(bob, game)
+
+  // but none of that time counts against him
+  t.equal((await move(game, alice, "ClaimTimeout")).Ok, undefined)
+  t.equal((await move(game, alice, "ClaimAbandonment")).Ok, undefined)
+  t.equal((await move(game, bob, { Place: {x:0, y:0, direction:"Up"} })).Err, undefined)
+})
+
+diorama.registerScenario("Can claim a stalled game as abandoned", async(s, t, { alice, bob}) => {
+
+  // a game can't be backdated to start its window early
+  const backdated_game = await alice.callSync('main', 'create_game', {
+    opponent: bob.agentId,
+    timestamp: Date.now() - 60000,
+    settings: {
+      width: 3,
+      height: 3,
+      abandon_after_ms: 10000,
+    },
+  })
+  t.equal(backdated_game.Ok, undefined)
+
+  // bob has ten seconds to make the first move
+  const game = await createGame(t, alice, bob, { abandon_after_ms: 10000 })
+
+  t.equal((await move(game, alice, "ClaimAbandonment")).Ok, undefined)
+  // a claim dated after the window has passed is rejected until it really has
+  t.equal((await move(game, alice, "ClaimAbandonment", Date.now() + 10000)).Ok, undefined)
+
+  // offering a draw doesn't end bob's turn, so it doesn't hold the window open
+  await sleep(5000)
+  t.equal((await move(game, bob, "OfferDraw")).Err, undefined)
+  await sleep(5000)
+  t.equal((await move(game, alice, "ClaimAbandonment")).Err, undefined)
+
+  const alices_games = await alice.callSync('main', 'get_my_games', {})
+  console.log(JSON.stringify(alices_games))
+  t.equal(alices_games.Ok[0].status, "Abandoned")
+  t.equal(alices_games.Ok[0].result, "Player1Wins")
+})
+
+diorama.registerScenario("Players can agree to a draw", async(s, t, { alice, bob}) => {
+
+  const game = await createGame(t, alice, bob)
+
+  // alice offers a draw before bob has even moved
+  t.equal((await move(game, alice, "OfferDraw")).Err, undefined)
+  // she can't accept her own offer
+  t.equal((await move(game, alice, "AcceptDraw")).Ok, undefined)
+  // the offer didn't use up a turn so bob still moves first
+  t.equal((await move(game, bob, { Place: {x:0, y:0, direction:"Up"} })).Err, undefined)
+  // placing a line declined the offer, so there is nothing to accept
+  t.equal((await move(game, bob, "AcceptDraw")).Ok, undefined)
+
+  t.equal((await move(game, alice, "OfferDraw")).Err, undefined)
+  t.equal((await move(game, bob, "AcceptDraw")).Err, undefined)
+
+  const game_state = await alice.callSync('main', 'get_state', {
+    game_address: game
+  })
+  console.log(JSON.stringify(game_state))
+  t.equal(game_state.Ok.status, "DrawAgreed")
+  t.equal(game_state.Ok.result, "Draw")
+})
+
+diorama.registerScenario("Players can agree to take back a line", async(s, t, { alice, bob}) => {
+
+  const game = await createGame(t, alice, bob)
+
+  // nothing to undo yet
+  t.equal((await move(game, bob, "RequestUndo")).Ok, undefined)
+
+  t.equal((await move(game, bob, { Place: {x:0, y:0, direction:"Up"} })).Err, undefined)
+  t.equal((await move(game, bob, "RequestUndo")).Err, undefined)
+  // bob can't approve his own request
+  t.equal((await move(game, bob, "ApproveUndo")).Ok, undefined)
+  t.equal((await move(game, alice, "ApproveUndo")).Err, undefined)
+
+  const game_state = await alice.callSync('main', 'get_state', {
+    game_address: game
+  })
+  console.log(JSON.stringify(game_state))
+  t.equal(game_state.Ok.lines.length, 0)
+  t.equal(game_state.Ok.moves.length, 3)
+  t.equal(game_state.Ok.next_player, 2)
+
+  // bob gets to place his line again
+  t.equal((await move(game, bob, { Place: {x:1, y:0, direction:"Up"} })).Err, undefined)
+
+  // in a timed game the clocks go back to how they were before the line, increment and all
+  const timed_game = await createGame(t, alice, bob, { time_control: { initial_ms: 60000, increment_ms: 5000 } })
+  t.equal((await move(timed_game, bob, { Place: {x:0, y:0, direction:"Up"} })).Err, undefined)
+  t.equal((await move(timed_game, bob, "RequestUndo")).Err, undefined)
+  t.equal((await move(timed_game, alice, "ApproveUndo")).Err, undefined)
+
+  const timed_state = await alice.callSync('main', 'get_state', {
+    game_address: timed_game
+  })
+  console.log(JSON.stringify(timed_state))
+  t.equal(timed_state.Ok.player2_time_ms, 60000)
+  t.equal(timed_state.Ok.player1_time_ms, 60000)
+})
+
+diorama.registerScenario("Competing moves are reported as a fork and the earliest is followed", async(s, t, { alice, bob}) => {
+
+  const game = await createGame(t, alice, bob)
+
+  // both players make the first move at once, so neither sees the other's and both follow the game
+  // (call rather than the callSync in move, so neither waits for the other to be gossiped)
+  const now = Date.now()
+  const [offer_result, place_result] = await Promise.all([
+    alice.call('main', 'make_move', {
+      new_move: { game: game, move_type: "OfferDraw", timestamp: now - 1000 }
+    }),
+    bob.call('main', 'make_move', {
+      new_move: { game: game, move_type: { Place: {x:0, y:0, direction:"Up"} }, timestamp: now }
+    }),
+  ])
+  t.equal(offer_result.Err, undefined)
+  t.equal(place_result.Err, undefined)
+  await s.consistent()
+
+  const forks = await bob.callSync('main', 'get_forks', { game_address: game })
+  console.log(JSON.stringify(forks))
+  t.equal(forks.Ok.length, 1)
+  t.equal(forks.Ok[0].previous_move, game)
+  t.equal(forks.Ok[0].branches.length, 2)
+  t.equal(forks.Ok[0].canonical, forks.Ok[0].branches[0])
+
+  // alice's offer has the earlier timestamp so both players follow it and bob's line is left out
+  for (const player of [alice, bob]) {
+    const game_state = await player.callSync('main', 'get_state', {
+      game_address: game
+    })
+    console.log(JSON.stringify(game_state))
+    t.equal(game_state.Ok.moves.length, 1)
+    t.equal(game_state.Ok.moves[0].author, alice.agentId)
+    t.equal(game_state.Ok.draw_offered_by, 1)
+    t.equal(game_state.Ok.lines.length, 0)
+  }
+})
+
+diorama.registerScenario("A coin flip can decide who goes first", async(s, t, { alice, bob}) => {
+
+  const creator_first = await createGame(t, alice, bob, { first_player: "Creator" }, false)
+  const creator_state = await alice.callSync('main', 'get_state', {
+    game_address: creator_first
+  })
+  t.equal(creator_state.Ok.next_player, 1)
+
+  const game = await createGame(t, alice, bob, { first_player: "Random" })
+
+  // no lines until the coin flip is settled
+  t.equal((await move(game, bob, { Place: {x:0, y:0, direction:"Up"} })).Ok, undefined)
+  // only bob seeds and alice can't reveal before the seed
+  t.equal((await move(game, alice, { CoinFlipSeed: {seed: "alice"} })).Ok, undefined)
+  t.equal((await move(game, alice, { CoinFlipReveal: {secret: "anything"} })).Ok, undefined)
+  t.equal((await move(game, bob, { CoinFlipSeed: {seed: "heads"} })).Err, undefined)
+  // alice can't reveal a secret she didn't commit to
+  t.equal((await move(game, alice, { CoinFlipReveal: {secret: "anything"} })).Ok, undefined)
+
+  const reveal_result = await alice.callSync('main', 'reveal_coin_flip', {
+    game_address: game,
+    timestamp: Date.now(),
+  })
+  t.equal(reveal_result.Err, undefined)
+
+  const game_state = await alice.callSync('main', 'get_state', {
+    game_address: game
+  })
+  console.log(JSON.stringify(game_state))
+  t.equal(game_state.Ok.coin_flip, null)
+
+  // whoever won the flip can place the first line
+  const first_player = game_state.Ok.next_player == 1 ? alice : bob
+  t.equal((await move(game, first_player, { Place: {x:0, y:0, direction:"Up"} })).Err, undefined)
+})
+
+diorama.registerScenario("Player 2 goes first if player 1 doesn't reveal the coin flip", async(s, t, { alice, bob}) => {
+
+  const game = await createGame(t, alice, bob, { first_player: "Random", coin_flip_reveal_ms: 10000 })
+
+  t.equal((await move(game, bob, { CoinFlipSeed: {seed: "heads"} })).Err, undefined)
+  // alice still has time to reveal, and only bob can claim
+  t.equal((await move(game, bob, "ClaimCoinFlip")).Ok, undefined)
+  t.equal((await move(game, bob, "ClaimCoinFlip", Date.now() + 10000)).Ok, undefined)
+
+  await sleep(10000)
+  t.equal((await move(game, alice, "ClaimCoinFlip")).Ok, undefined)
+  t.equal((await move(game, bob, "ClaimCoinFlip")).Err, undefined)
+
+  // too late to reveal now
+  const reveal_result = await alice.callSync('main', 'reveal_coin_flip', {
+    game_address: game,
+    timestamp: Date.now(),
+  })
+  t.equal(reveal_result.Ok, undefined)
+
+  const game_state = await alice.callSync('main', 'get_state', {
+    game_address: game
+  })
+  console.log(JSON.stringify(game_state))
+  t.equal(game_state.Ok.coin_flip, null)
+  t.equal(game_state.Ok.next_player, 2)
+  t.equal((await move(game, bob, { Place: {x:0, y:0, direction:"Up"} })).Err, undefined)
+})
+
+diorama.registerScenario("A declined invitation never starts the game", async(s, t, { alice, bob}) => {
+
+  const game = await createGame(t, alice, bob, {}, false)
+
+  const invitations = await bob.callSync('main', 'get_invitations', {})
+  t.equal(invitations.Ok.length, 1)
+  const decline_result = await bob.callSync('main', 'decline_invitation', { invitation_addr: invitations.Ok[0].address })
+  t.equal(decline_result.Err, undefined)
+  // once declined it can't be accepted
+  const accept_result = await bob.callSync('main', 'accept_invitation', { invitation_addr: invitations.Ok[0].address })
+  t.equal(accept_result.Ok, undefined)
+
+  t.equal((await move(game, bob, { Place: {x:0, y:0, direction:"Up"} })).Ok, undefined)
+})
+
+diorama.registerScenario("Accepting a proposal creates a game with its settings", async(s, t, { alice, bob}) => {
+
+  const proposal_result = await bob.callSync('main', 'create_proposal', {
+    message: "Misere on a small board, I'd like to go second",
+    settings: {
+      width: 4,
+      height: 3,
+      variant: "Misere",
+      rated: true,
+      first_player: "Creator",
+    },
+    expires_at: Date.now() + 60 * 60 * 1000,
+  })
+  t.equal(proposal_result.Ok.length, 46)
+
+  const misere_proposals = await alice.callSync('main', 'get_proposals', { filter: { variant: "Misere", width: 4 }, now: Date.now() })
+  t.equal(misere_proposals.Ok.length, 1)
+  t.equal(misere_proposals.Ok[0].entry.settings.height, 3)
+  const unrated_proposals = await alice.callSync('main', 'get_proposals', { filter: { rated: false }, now: Date.now() })
+  t.equal(unrated_proposals.Ok.length, 0)
+  const all_proposals = await alice.callSync('main', 'get_proposals', { now: Date.now() })
+  t.equal(all_proposals.Ok.length, 1)
+  // the proposal can't be seen or accepted once it has expired
+  const expires_at = misere_proposals.Ok[0].entry.expires_at
+  const later_proposals = await alice.callSync('main', 'get_proposals', { now: expires_at })
+  t.equal(later_proposals.Ok.length, 0)
+  const late_accept = await alice.callSync('main', 'accept_proposal', {
+    proposal_addr: proposal_result.Ok,
+    created_at: expires_at,
+  })
+  t.equal(late_accept.Ok, undefined)
+
+  const accept_result = await alice.callSync('main', 'accept_proposal', {
+    proposal_addr: proposal_result.Ok,
+    created_at: Date.now(),
+  })
+  t.equal(accept_result.Ok.length, 46)
+  // the proposal is listed until bob accepts a game made from it
+  t.equal((await alice.callSync('main', 'get_proposals', { now: Date.now() })).Ok.length, 1)
+  await acceptInvitation(bob, accept_result.Ok)
+  // accepted proposals drop out of the list
+  t.equal((await alice.callSync('main', 'get_proposals', { now: Date.now() })).Ok.length, 0)
+
+  // alice created the game so she is player 1 and goes first
+  const game_state = await alice.callSync('main', 'get_state', {
+    game_address: accept_result.Ok
+  })
+  console.log(JSON.stringify(game_state))
+  t.equal(game_state.Ok.width, 4)
+  t.equal(game_state.Ok.height, 3)
+  t.equal(game_state.Ok.next_player, 1)
+})
+
+diorama.registerScenario("Only the proposer can remove a proposal", async(s, t, { alice, bob}) => {
+
+  const proposal_result = await bob.callSync('main', 'create_proposal', {
+    message: "Anyone for a quick game?",
+    settings: { width: 3, height: 3 },
+    expires_at: Date.now() + 60 * 60 * 1000,
+  })
+  t.equal(proposal_result.Ok.length, 46)
+
+  const alice_remove = await alice.callSync('main', 'remove_proposal', { proposal_addr: proposal_result.Ok })
+  t.equal(alice_remove.Ok, undefined)
+  t.equal((await alice.callSync('main', 'get_proposals', { now: Date.now() })).Ok.length, 1)
+
+  const bob_remove = await bob.callSync('main', 'remove_proposal', { proposal_addr: proposal_result.Ok })
+  t.equal(bob_remove.Err, undefined)
+  t.equal((await alice.callSync('main', 'get_proposals', { now: Date.now() })).Ok.length, 0)
+})
+
+diorama.registerScenario("A proposer confirms only one of the games made from a proposal", async(s, t, { alice, bob}) => {
+
+  const proposal_result = await bob.callSync('main', 'create_proposal', {
+    message: "Looking for one game",
+    settings: { width: 3, height: 3 },
+    expires_at: Date.now() + 60 * 60 * 1000,
+  })
+  t.equal(proposal_result.Ok.length, 46)
+
+  // alice accepts twice, making two competing games
+  const first_game = await alice.callSync('main', 'accept_proposal', { proposal_addr: proposal_result.Ok, created_at: Date.now() })
+  const second_game = await alice.callSync('main', 'accept_proposal', { proposal_addr: proposal_result.Ok, created_at: Date.now() })
+  t.equal(first_game.Ok.length, 46)
+  t.equal(second_game.Ok.length, 46)
+
+  const pending = await bob.callSync('main', 'check_responses', { proposal_addr: proposal_result.Ok })
+  t.equal(pending.Ok.length, 2)
+  t.deepEqual(pending.Ok.map(response => response.status), ["Pending", "Pending"])
+
+  // bob picks the second game which declines the first
+  t.equal((await acceptInvitation(bob, second_game.Ok)).Err, undefined)
+  const responses = await alice.callSync('main', 'check_responses', { proposal_addr: proposal_result.Ok })
+  console.log(JSON.stringify(responses))
+  t.equal(responses.Ok.find(response => response.address == second_game.Ok).status, "Accepted")
+  t.equal(responses.Ok.find(response => response.address == second_game.Ok).chosen, true)
+  t.equal(responses.Ok.find(response => response.address == first_game.Ok).status, "Declined")
+  t.equal((await bob.callSync('main', 'get_invitations', {})).Ok.length, 0)
+
+  // the declined game can't be played and the proposal can't be accepted again
+  t.equal((await move(first_game.Ok, bob, { Place: {x:0, y:0, direction:"Up"} })).Ok, undefined)
+  const third_game = await alice.callSync('main', 'accept_proposal', { proposal_addr: proposal_result.Ok, created_at: Date.now() })
+  t.equal(third_game.Ok, undefined)
 })
 
 diorama.run()
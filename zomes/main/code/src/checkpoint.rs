@@ -0,0 +1,200 @@
+use hdk::{
+    utils,
+    entry_definition::ValidatingEntryType,
+    error::{ZomeApiResult, ZomeApiError},
+    holochain_persistence_api::{
+        cas::content::{Address},
+    },
+    holochain_json_api::{
+        error::JsonError, json::JsonString,
+    },
+    holochain_core_types::{
+        dna::entry_types::Sharing,
+        validation::EntryValidationData,
+        entry::Entry,
+        link::LinkMatch,
+    }
+};
+
+use crate::game::{self, Game};
+use crate::game_move::Move;
+use crate::GameState;
+
+/// How many moves are made between each snapshot of the game state
+pub const CHECKPOINT_INTERVAL: usize = 10;
+
+/// A snapshot of the state of a game after a particular move. Loading the state can start
+/// from here instead of replaying every move since the game was created. Each checkpoint
+/// follows on from the one CHECKPOINT_INTERVAL moves before it, so it can be checked by
+/// replaying just the moves in between.
+#[derive(Clone, Debug, Serialize, Deserialize, DefaultJson)]
+pub struct Checkpoint {
+    pub game: Address,
+    pub last_move: Address,
+    pub previous: Option<Address>, // the checkpoint before this one, None for the first in a game
+    pub state: GameState, // stored without its list of moves, those are all in the move chain
+}
+
+/// Commit a checkpoint for the game if the state has reached the next checkpoint interval.
+/// `previous` is the checkpoint the state was loaded from. If that is more than one interval
+/// back the new checkpoint couldn't be checked against it, so none is made.
+pub fn create_checkpoint_if_due(game_address: &Address, last_move: &Address, state: &GameState, previous: Option<Address>) -> ZomeApiResult<()> {
+    if state.moves.len() != CHECKPOINT_INTERVAL || state.move_count % CHECKPOINT_INTERVAL != 0 {
+        return Ok(())
+    }
+    let checkpoint = Checkpoint {
+        game: game_address.clone(),
+        last_move: last_move.clone(),
+        previous,
+        state: GameState { moves: Vec::new(), ..state.clone() },
+    };
+    let checkpoint_entry = Entry::App(
+        "checkpoint".into(),
+        checkpoint.into(),
+    );
+    let checkpoint_address = hdk::commit_entry(&checkpoint_entry)?;
+    // the tag is the number of moves covered so the latest can be picked without loading them all
+    hdk::link_entries(game_address, &checkpoint_address, "has_checkpoint", state.move_count.to_string())?;
+    Ok(())
+}
+
+/// The checkpoint covering the most moves of a game and its address, if any have been made yet.
+/// Only checkpoints whose moves since the previous checkpoint are on the canonical branch are used,
+/// so loading from one gives the same state as walking the move chain.
+pub fn get_latest_checkpoint(game_address: &Address, game: &Game) -> ZomeApiResult<Option<(Address, Checkpoint)>> {
+    let mut links = hdk::get_links(game_address, LinkMatch::Exactly("has_checkpoint"), LinkMatch::Any)?.links();
+    links.sort_by_key(|link| std::cmp::Reverse(link.tag.parse::<usize>().unwrap_or(0)));
+    for link in links {
+        let mut checkpoint: Checkpoint = utils::get_as_type(link.address.clone())?;
+        if &checkpoint.game != game_address {
+            continue
+        }
+        if is_on_canonical_branch(game, &checkpoint)? {
+            checkpoint.state.rebuild_line_set();
+            return Ok(Some((link.address, checkpoint)))
+        }
+    }
+    Ok(None)
+}
+
+/// Whether each move since the previous checkpoint is the one walk_move_chain would pick where
+/// the chain forks. Only the links from those moves are fetched, plus the competing moves at a fork.
+fn is_on_canonical_branch(game: &Game, checkpoint: &Checkpoint) -> ZomeApiResult<bool> {
+    let (mut state, start) = get_starting_point(game, checkpoint)?;
+    for game_move in get_moves_since(&checkpoint.game, &start, &checkpoint.last_move)? {
+        let branches = hdk::get_links(&game_move.previous_move, LinkMatch::Exactly(""), LinkMatch::Any)?.addresses();
+        if branches.len() > 1 {
+            let mut candidates = branches
+                .into_iter()
+                .map(|addr| game::get_move(&addr).map(|branch_move| (addr, branch_move)))
+                .collect::<ZomeApiResult<Vec<(Address, Move)>>>()?;
+            game::sort_canonical(game, &state, &mut candidates);
+            if candidates[0].1 != game_move {
+                return Ok(false)
+            }
+        }
+        state = state.evolve(game.clone(), &game_move);
+    }
+    Ok(true)
+}
+
+/// The state a checkpoint's moves were played from and the entry they follow on from. That is
+/// the previous checkpoint, or the start of the game for the first one.
+fn get_starting_point(game: &Game, checkpoint: &Checkpoint) -> ZomeApiResult<(GameState, Address)> {
+    match &checkpoint.previous {
+        Some(previous_address) => {
+            let mut previous: Checkpoint = utils::get_as_type(previous_address.clone())?;
+            if previous.game != checkpoint.game {
+                return Err(ZomeApiError::Internal("The previous checkpoint belongs to a different game".into()))
+            }
+            previous.state.rebuild_line_set();
+            Ok((previous.state, previous.last_move))
+        },
+        None => Ok((GameState::initial(game), checkpoint.game.clone())),
+    }
+}
+
+/// The CHECKPOINT_INTERVAL moves leading up to `last_move`, oldest first, found by following each
+/// move's previous_move. They have to start right after `start`.
+fn get_moves_since(game_address: &Address, start: &Address, last_move: &Address) -> ZomeApiResult<Vec<Move>> {
+    let mut moves = Vec::new();
+    let mut current = last_move.clone();
+    while moves.len() < CHECKPOINT_INTERVAL {
+        let game_move = game::get_move(&current)?;
+        if &game_move.game != game_address {
+            return Err(ZomeApiError::Internal(format!("Move at {} belongs to a different game", current)))
+        }
+        current = game_move.previous_move.clone();
+        moves.push(game_move);
+    }
+    if &current != start {
+        return Err(ZomeApiError::Internal("A checkpoint has to be taken one interval after the previous one".into()))
+    }
+    moves.reverse();
+    Ok(moves)
+}
+
+pub fn definition() -> ValidatingEntryType {
+    entry!(
+        name: "checkpoint",
+        description: "A snapshot of the state of a game after a given move",
+        sharing: Sharing::Public,
+        validation_package: || {
+            hdk::ValidationPackageDefinition::Entry
+        },
+
+        validation: | validation_data: hdk::EntryValidationData<Checkpoint>| {
+            match validation_data {
+                EntryValidationData::Create{entry, validation_data: _} => {
+                    let checkpoint = entry as Checkpoint;
+                    // A checkpoint is only valid if replaying the moves since the previous checkpoint
+                    // on top of its state gives the same state
+                    let game = game::get_game(&checkpoint.game)
+                        .map_err(|_| "Could not load game during validation")?;
+                    let (start_state, start) = get_starting_point(&game, &checkpoint)
+                        .map_err(|_| "Could not load the previous checkpoint during validation")?;
+                    let moves = get_moves_since(&checkpoint.game, &start, &checkpoint.last_move)
+                        .map_err(|_| "Could not load the moves since the previous checkpoint during validation")?;
+                    let derived = moves.iter().fold(start_state, |state, new_move| state.evolve(game.clone(), new_move));
+                    if JsonString::from(GameState { moves: Vec::new(), ..derived }) == JsonString::from(checkpoint.state) {
+                        Ok(())
+                    } else {
+                        Err("Checkpoint state does not match the moves it was taken from".into())
+                    }
+                },
+                _ => {
+                    Err("Cannot modify or delete a checkpoint".into())
+                }
+            }
+        },
+
+        links: [
+            from!(
+                "game",
+                link_type: "has_checkpoint",
+                validation_package: || {
+                    hdk::ValidationPackageDefinition::Entry
+                },
+                validation: | validation_data: hdk::LinkValidationData| {
+                    match validation_data {
+                        // a checkpoint can only be linked from the game it was taken in, tagged with how many moves it covers
+                        hdk::LinkValidationData::LinkAdd{link, validation_data: _} => {
+                            let checkpoint: Checkpoint = utils::get_as_type(link.link.target().clone())
+                                .map_err(|_| "Could not load the linked checkpoint during validation")?;
+                            if &checkpoint.game != link.link.base() {
+                                Err("A checkpoint can only be linked from its own game".into())
+                            } else if link.link.tag() != &checkpoint.state.move_count.to_string() {
+                                Err("A checkpoint link has to be tagged with the number of moves the checkpoint covers".into())
+                            } else {
+                                Ok(())
+                            }
+                        },
+                        hdk::LinkValidationData::LinkRemove{..} => {
+                            Err("Cannot remove a link to a checkpoint".into())
+                        }
+                    }
+                }
+            )
+        ]
+    )
+}
@@ -1,136 +1,378 @@
-use std::convert::TryFrom;
 use hdk::{
     utils,
+    AGENT_ADDRESS,
     entry_definition::ValidatingEntryType,
     error::{ZomeApiResult, ZomeApiError},
     holochain_persistence_api::{
         cas::content::{AddressableContent, Address},
     },
     holochain_json_api::{
-        error::JsonError, json::JsonString,
+        error::JsonError, json::{JsonString, RawString},
     },
     holochain_core_types::{
         dna::entry_types::Sharing,
-        validation::EntryValidationData,
+        validation::{EntryValidationData, ValidationData},
+        chain_header::ChainHeader,
         entry::Entry,
         link::LinkMatch,
     }
 };
 
+use chrono::DateTime;
+
 use crate::game_move::Move;
+use crate::checkpoint::get_latest_checkpoint;
+use crate::invitation::{self, Invitation, InvitationStatus};
+use crate::matchmaking::GameProposal;
 use crate::GameState;
+use crate::your_game::state::{MIN_BOARD_SIZE, MAX_BOARD_SIZE, GameStatus, GameResult};
 
 #[derive(Clone, Debug, Serialize, Deserialize, DefaultJson)]
 pub struct Game {
     pub player_1: Address,
     pub player_2: Address,
-    pub created_at: u32,
+    pub created_at: u64, // milliseconds since the unix epoch
+    pub settings: GameSettings,
+    pub coin_flip_commitment: Option<Address>, // hash of player 1's coin flip secret when the first player is Random
+    pub proposal: Option<Address>, // the proposal this game was created by accepting, if any
 }
 
-/*=====================================
-=            DHT Functions            =
-=====================================*/
+/// Everything about how a game is played that the players agree on up front. Proposals carry
+/// the same settings so accepting one creates exactly the game that was advertised.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct GameSettings {
+    pub width: usize, // number of grid points along the x axis
+    pub height: usize, // number of grid points along the y axis
+    pub time_control: Option<TimeControl>, // None for an untimed game
+    pub abandon_after_ms: Option<u64>, // inactivity before a game can be claimed as abandoned, None for the default
+    pub coin_flip_reveal_ms: Option<u64>, // time player 1 has to reveal the coin flip after the seed, None for the default
+    #[serde(default)]
+    pub variant: RuleVariant,
+    #[serde(default)]
+    pub rated: bool, // whether the result should count towards the players' ratings
+    #[serde(default)]
+    pub first_player: FirstPlayer,
+}
 
-/// Traverse the linked list rooted at a game to find all the moves
-pub fn get_moves(game_address: &Address) -> ZomeApiResult<Vec<Move>> {
-    match hdk::get_links(game_address, LinkMatch::Any, LinkMatch::Any)?.addresses().into_iter().next() {
-        Some(first_move) => {
-            let mut move_addresses = vec![first_move];
-            let mut more = true;
-            while more {
-                more = match hdk::get_links(move_addresses.last().unwrap(), LinkMatch::Any, LinkMatch::Any)?.addresses().into_iter().next() {
-                    Some(addr) => {
-                        move_addresses.push(addr.clone());
-                        true
-                    },
-                    None => {
-                        false
-                    },
-                }
+/// Who makes the first move. The creator of a game is always player 1 and their opponent player 2.
+/// When a proposal is accepted the accepting agent creates the game, so Opponent is the proposer.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum FirstPlayer {
+    Creator,
+    Opponent,
+    Random, // decided by a commit-reveal coin flip between the two players
+}
+
+/// By convention the opponent goes first unless the creator chooses otherwise
+impl Default for FirstPlayer {
+    fn default() -> Self {
+        FirstPlayer::Opponent
+    }
+}
+
+/// How the winner is decided once every box has been completed
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum RuleVariant {
+    Standard, // the player with the most boxes wins
+    Misere, // the player with the fewest boxes wins
+}
+
+impl Default for RuleVariant {
+    fn default() -> Self {
+        RuleVariant::Standard
+    }
+}
+
+/// How long the player to move can go without moving before the game can be claimed as abandoned
+pub const DEFAULT_ABANDON_AFTER_MS: u64 = 3 * 24 * 60 * 60 * 1000;
+
+/// How long player 1 has to reveal the coin flip once player 2 has seeded it. The reveal needs no
+/// thought, so this is only long enough for player 1 to come back online.
+pub const DEFAULT_COIN_FLIP_REVEAL_MS: u64 = 10 * 60 * 1000;
+
+/// Chess style clock settings. Each player starts with `initial_ms` on their clock and gets
+/// `increment_ms` added back each time they place a line.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TimeControl {
+    pub initial_ms: u64,
+    pub increment_ms: u64,
+}
+
+/// Competing moves that were all linked from the same previous move
+#[derive(Clone, Debug, Serialize, Deserialize, DefaultJson)]
+pub struct Fork {
+    pub previous_move: Address, // the game or move the branches were linked from
+    pub branches: Vec<Address>, // first move of each branch, canonical one first
+    pub canonical: Address, // the branch get_state follows
+}
+
+/// One entry in an agent's list of games
+#[derive(Clone, Debug, Serialize, Deserialize, DefaultJson)]
+pub struct GameSummary {
+    pub address: Address,
+    pub opponent: Address,
+    pub status: GameStatus,
+    pub result: GameResult,
+    pub invitation: InvitationStatus, // whether player 2 has accepted the game
+    pub my_turn: bool, // only ever set once the game has been accepted
+}
+
+impl GameSettings {
+    /// Checks shared by games and the proposals that advertise them
+    pub fn validate(&self) -> Result<(), String> {
+        if self.width < MIN_BOARD_SIZE || self.width > MAX_BOARD_SIZE
+            || self.height < MIN_BOARD_SIZE || self.height > MAX_BOARD_SIZE {
+            return Err(format!("Board must have between {} and {} grid points on each side.", MIN_BOARD_SIZE, MAX_BOARD_SIZE))
+        }
+        if self.abandon_after_ms == Some(0) {
+            return Err("The inactivity window for abandoning a game must be longer than zero.".into())
+        }
+        if self.coin_flip_reveal_ms == Some(0) {
+            return Err("The time to reveal the coin flip must be longer than zero.".into())
+        }
+        if let Some(time_control) = &self.time_control {
+            if time_control.initial_ms == 0 {
+                return Err("A timed game must start with some time on the clock.".into())
             }
-            let moves: Vec<Move> = move_addresses.iter().map(|addr| {
-                let move_entry = hdk::get_entry(addr).unwrap().unwrap();
-                if let Entry::App(_, move_struct) = move_entry {
-                    Move::try_from(move_struct).expect("Entry at address is type other than Move")
-                } else {
-                    panic!("Not an app entry!")
-                }
-            }).collect();
-            Ok(moves)
-        },
-        None => {
-            Ok(Vec::new())
         }
+        Ok(())
     }
 }
 
-pub fn get_state(game_address: &Address) -> ZomeApiResult<GameState> {
-    let moves = get_moves(game_address)?;
-    let game = get_game(game_address)?;
-    let new_state = moves.iter().fold(GameState::initial(), |state, new_move| state.evolve(game.clone(), new_move));
-    Ok(new_state)
+impl Game {
+    pub fn abandon_after(&self) -> u64 {
+        self.settings.abandon_after_ms.unwrap_or(DEFAULT_ABANDON_AFTER_MS)
+    }
+
+    pub fn coin_flip_reveal_within(&self) -> u64 {
+        self.settings.coin_flip_reveal_ms.unwrap_or(DEFAULT_COIN_FLIP_REVEAL_MS)
+    }
+
+    /// Returns 1 or 2 depending on which player the agent is, or None if they are not in this game
+    pub fn player_number(&self, agent: &Address) -> Option<usize> {
+        if &self.player_1 == agent {
+            Some(1)
+        } else if &self.player_2 == agent {
+            Some(2)
+        } else {
+            None
+        }
+    }
 }
 
-pub fn get_game(game_address: &Address) -> ZomeApiResult<Game> {
-    utils::get_as_type(game_address.to_owned())
+/*=====================================
+=             Coin flip               =
+=====================================*/
+
+// Deciding who goes first at random works by commit-reveal. Player 1 publishes the hash of a secret
+// in the game, player 2 then picks a seed, and finally player 1 reveals the secret. Player 1 can't
+// change the secret after seeing the seed and player 2 can't predict it, so neither can bias the flip.
+// Player 1 could still refuse to reveal once they see the seed would lose them the flip, so if they
+// don't reveal in time player 2 can claim the first move instead.
+
+/// Player 1's secret for the coin flip. Signatures are deterministic so it can be recomputed when it
+/// is time to reveal it, and nobody without player 1's key can work it out from the commitment.
+pub fn coin_flip_secret(opponent: &Address, created_at: u64) -> ZomeApiResult<String> {
+    hdk::sign(format!("coin flip against {} at {}", opponent, created_at))
 }
 
-/*=====  End of DHT Functions  ======*/
+/// Hash of a secret that is safe to publish before the secret itself
+pub fn coin_flip_commitment(secret: &str) -> Address {
+    Entry::App("coin_flip".into(), RawString::from(secret).into()).address()
+}
 
+/// The commitment to store in a new game, if its first player is decided at random
+pub fn new_coin_flip_commitment(first_player: &FirstPlayer, opponent: &Address, created_at: u64) -> ZomeApiResult<Option<Address>> {
+    match first_player {
+        FirstPlayer::Random => Ok(Some(coin_flip_commitment(&coin_flip_secret(opponent, created_at)?))),
+        _ => Ok(None),
+    }
+}
 
+/// Combine both halves of the coin flip into the number of the player who goes first
+pub fn coin_flip_winner(secret: &str, seed: &str) -> usize {
+    let combined = coin_flip_commitment(&format!("{}{}", secret, seed)).to_string();
+    match combined.bytes().last() {
+        Some(byte) if byte % 2 == 0 => 1,
+        _ => 2,
+    }
+}
 
-/*=============================================
-=            Local chain functions            =
-=============================================*/
+/*=====  End of Coin flip  ======*/
 
-pub fn get_game_local_chain(local_chain: Vec<Entry>, game_address: &Address) -> ZomeApiResult<Game> {
-    local_chain
-        .iter()
-        .filter(|entry| {
-            entry.address() == game_address.to_owned()
-        })
-        .filter_map(|entry| {
-            if let Entry::App(_, entry_data) = entry {
-                Some(Game::try_from(entry_data.clone()).unwrap())
-            } else {
-                None
-            }
-        })
-        .next()
-        .ok_or(ZomeApiError::HashNotFound)
-}
-
-pub fn get_moves_local_chain(local_chain: Vec<Entry>, game_address: &Address) -> ZomeApiResult<Vec<Move>> {
-    Ok(local_chain
-        .iter()
-        .filter_map(|entry| {
-            if let Entry::App(entry_type, entry_data) = entry {
-                if entry_type.to_string() == "move" {
-                    Some(Move::try_from(entry_data.clone()).unwrap())
-                } else {
-                    None
-                }
-            } else {
-                None
-            }
-        })
-        .filter(|game_move| {
-            game_move.game == game_address.to_owned()
+/*=====================================
+=             Timestamps              =
+=====================================*/
+
+// Clocks and the abandonment window are worked out from the timestamps players give their moves,
+// so those timestamps are only accepted if they agree with the time the author's conductor wrote
+// into the header of the entry.
+
+/// How far a timestamp can be from its entry's header time. This is as much time as a player can
+/// gain on their clock, or take from their opponent's, by misdating a move.
+pub const MAX_TIMESTAMP_DRIFT_MS: u64 = 2000;
+
+/// The time in a chain header, in milliseconds since the unix epoch
+pub fn header_time_ms(header: &ChainHeader) -> Result<u64, String> {
+    let header_time = DateTime::parse_from_rfc3339(&header.timestamp().to_string())
+        .map_err(|_| "Could not read the header timestamp")?;
+    Ok(header_time.timestamp() as u64 * 1000 + u64::from(header_time.timestamp_subsec_millis()))
+}
+
+/// Check a timestamp given in an entry is within MAX_TIMESTAMP_DRIFT_MS of when it was committed
+pub fn validate_timestamp(timestamp: u64, validation_data: &ValidationData) -> Result<(), String> {
+    let header_time = header_time_ms(&validation_data.package.chain_header)?;
+    let drift = if timestamp > header_time { timestamp - header_time } else { header_time - timestamp };
+    if drift > MAX_TIMESTAMP_DRIFT_MS {
+        return Err(format!("Timestamp must be within {}ms of the time the entry was committed", MAX_TIMESTAMP_DRIFT_MS))
+    }
+    Ok(())
+}
+
+/*=====  End of Timestamps  ======*/
+
+/*=====================================
+=            DHT Functions            =
+=====================================*/
+
+/// Commit a new game and link it from both players so it shows up in their list of games.
+/// Player 2 is sent an invitation and the game can't start until they accept it.
+pub fn create_game(game: Game) -> ZomeApiResult<Address> {
+    let game_entry = Entry::App(
+        "game".into(),
+        game.clone().into(),
+    );
+    let game_address = hdk::commit_entry(&game_entry)?;
+    hdk::link_entries(&game.player_1, &game_address, "has_game", "")?;
+    hdk::link_entries(&game.player_2, &game_address, "has_game", "")?;
+    invitation::create_invitation(&game_address, &game)?;
+    Ok(game_address)
+}
+
+/// List all of the games the current agent is playing or has played
+pub fn get_my_games() -> ZomeApiResult<Vec<GameSummary>> {
+    let me: Address = AGENT_ADDRESS.to_string().into();
+    hdk::get_links(&me, LinkMatch::Exactly("has_game"), LinkMatch::Any)?
+        .addresses()
+        .into_iter()
+        .map(|game_address| -> ZomeApiResult<GameSummary> {
+            let game = get_game(&game_address)?;
+            let state = get_state_of(&game_address, &game)?;
+            let my_number = game.player_number(&me);
+            let invitation = invitation::get_status(&Invitation::for_game(&game_address, &game).address())?;
+            Ok(GameSummary {
+                opponent: if my_number == Some(1) { game.player_2 } else { game.player_1 },
+                status: state.status,
+                result: state.result,
+                my_turn: invitation == InvitationStatus::Accepted && my_number == Some(state.next_player),
+                invitation,
+                address: game_address,
+            })
         })
-        .rev()
-        .collect())
+        .collect()
 }
 
-pub fn get_state_local_chain(local_chain: Vec<Entry>, game_address: &Address) -> ZomeApiResult<GameState> {
-    let moves = get_moves_local_chain(local_chain.clone(), game_address)?;
-    let game = get_game_local_chain(local_chain, game_address)?;
-    let new_state = moves.iter().fold(GameState::initial(), move |state, new_move| state.evolve(game.clone(), new_move));
-    Ok(new_state)
+/// Load the state of a game, starting from its latest checkpoint so only the moves made
+/// since then have to be fetched and replayed
+pub fn get_state(game_address: &Address) -> ZomeApiResult<GameState> {
+    get_state_of(game_address, &get_game(game_address)?)
 }
 
+/// Same as get_state for callers that have already loaded the game
+pub fn get_state_of(game_address: &Address, game: &Game) -> ZomeApiResult<GameState> {
+    Ok(get_state_and_checkpoint(game_address, game)?.0)
+}
 
-/*=====  End of Local chain functions  ======*/
+/// Same as get_state_of, also giving the address of the checkpoint the state was loaded from.
+/// The state's moves are the ones made since that checkpoint.
+pub fn get_state_and_checkpoint(game_address: &Address, game: &Game) -> ZomeApiResult<(GameState, Option<Address>)> {
+    let (state, start, checkpoint_address) = match get_latest_checkpoint(game_address, game)? {
+        Some((checkpoint_address, checkpoint)) => (checkpoint.state, checkpoint.last_move, Some(checkpoint_address)),
+        None => (GameState::initial(game), game_address.clone(), None),
+    };
+    Ok((walk_move_chain(game, state, start)?.0, checkpoint_address))
+}
 
+/// Find every place in a game's move chain where more than one move follows the same entry.
+/// This always walks the whole chain from the game, as a fork can be anywhere in it.
+pub fn get_forks(game_address: &Address) -> ZomeApiResult<Vec<Fork>> {
+    let game = get_game(game_address)?;
+    Ok(walk_move_chain(&game, GameState::initial(&game), game_address.clone())?.1)
+}
+
+/// Rebuild the state of a game up to and including a given move by following each move's
+/// previous_move back to the game. Entries never change so this gives the same answer for
+/// every agent, unlike following links which may still be gossiping.
+pub fn get_state_ending_at(game_address: &Address, last_move: &Address) -> ZomeApiResult<GameState> {
+    let game = get_game(game_address)?;
+    let mut moves = Vec::new();
+    let mut current = last_move.clone();
+    while &current != game_address {
+        let game_move = get_move(&current)?;
+        if &game_move.game != game_address {
+            return Err(ZomeApiError::Internal(format!("Move at {} belongs to a different game", current)))
+        }
+        current = game_move.previous_move.clone();
+        moves.push(game_move);
+    }
+    Ok(moves.iter().rev().fold(GameState::initial(&game), |state, new_move| state.evolve(game.clone(), new_move)))
+}
+
+/// Follow the links from a starting entry through each move, evolving the state as we go.
+/// If two or more moves are linked from the same entry (a race or someone cheating) the
+/// canonical one is followed and the competing branches are reported as a Fork.
+fn walk_move_chain(game: &Game, start_state: GameState, start: Address) -> ZomeApiResult<(GameState, Vec<Fork>)> {
+    let mut state = start_state;
+    let mut forks = Vec::new();
+    let mut current = start;
+    loop {
+        // moves are linked with an empty link type, the game also links to its checkpoints
+        let mut candidates = hdk::get_links(&current, LinkMatch::Exactly(""), LinkMatch::Any)?
+            .addresses()
+            .into_iter()
+            .map(|addr| get_move(&addr).map(|game_move| (addr, game_move)))
+            .collect::<ZomeApiResult<Vec<(Address, Move)>>>()?;
+        if candidates.is_empty() {
+            break;
+        }
+        if candidates.len() > 1 {
+            sort_canonical(game, &state, &mut candidates);
+            forks.push(Fork {
+                previous_move: current.clone(),
+                branches: candidates.iter().map(|(addr, _)| addr.clone()).collect(),
+                canonical: candidates[0].0.clone(),
+            });
+        }
+        let (next_address, next_move) = candidates.swap_remove(0);
+        state = state.evolve(game.clone(), &next_move);
+        current = next_address;
+    }
+    Ok((state, forks))
+}
+
+/// Orders competing moves so the canonical one is first. A move that is valid against the state
+/// it was made on beats one that isn't, then the earliest timestamp wins and finally the lowest
+/// address, so every agent picks the same branch.
+pub fn sort_canonical(game: &Game, state: &GameState, candidates: &mut Vec<(Address, Move)>) {
+    candidates.sort_by_key(|(addr, game_move)| {
+        let invalid = game_move.is_valid(game.clone(), state.clone()).is_err();
+        (invalid, game_move.timestamp, addr.to_string())
+    });
+}
+
+pub fn get_move(move_address: &Address) -> ZomeApiResult<Move> {
+    // a move that is linked but not yet gossiped to us is an error rather than a panic,
+    // so callers can retry once the DHT catches up
+    utils::get_as_type::<Move>(move_address.clone()).map_err(|e| {
+        ZomeApiError::Internal(format!("Could not load move at {}: {:?}", move_address, e))
+    })
+}
+
+pub fn get_game(game_address: &Address) -> ZomeApiResult<Game> {
+    utils::get_as_type(game_address.to_owned())
+}
+
+/*=====  End of DHT Functions  ======*/
 
 
 
@@ -145,17 +387,67 @@ pub fn definition() -> ValidatingEntryType {
 
         validation: | validation_data: hdk::EntryValidationData<Game>| {
             match validation_data {
-                EntryValidationData::Create{entry, validation_data: _} => {
+                EntryValidationData::Create{entry, validation_data} => {
                     let game = entry as Game;
                     if game.player_1 == game.player_2 {
                         return Err("Player 1 and Player 2 must be different agents.".into())
                     }
+                    // the first player's clock and the abandonment window start from created_at
+                    validate_timestamp(game.created_at, &validation_data)?;
+                    game.settings.validate()?;
+                    // a game made from a proposal has to be against the proposer, on their terms
+                    if let Some(proposal_addr) = &game.proposal {
+                        let proposal: GameProposal = utils::get_as_type(proposal_addr.clone())
+                            .map_err(|_| "Could not load proposal during validation")?;
+                        if proposal.agent != game.player_2 || proposal.settings != game.settings {
+                            return Err("A game made from a proposal must be against the proposer with the proposal's settings.".into())
+                        }
+                        // created_at is bound to the header time, so this is when the proposal was accepted
+                        if proposal.is_expired(game.created_at) {
+                            return Err("A game can't be made from a proposal after it has expired.".into())
+                        }
+                    }
+                    match (&game.settings.first_player, &game.coin_flip_commitment) {
+                        (FirstPlayer::Random, None) => {
+                            return Err("A random first player needs a coin flip commitment.".into())
+                        },
+                        (FirstPlayer::Creator, Some(_)) | (FirstPlayer::Opponent, Some(_)) => {
+                            return Err("A coin flip commitment is only used for a random first player.".into())
+                        },
+                        _ => {},
+                    }
                     Ok(())
                 },
                 _ => {
                     Err("Cannot modify or delete a game".into())
                 }
             }
-        }
+        },
+
+        links: [
+            from!(
+                "%agent_id",
+                link_type: "has_game",
+                validation_package: || {
+                    hdk::ValidationPackageDefinition::Entry
+                },
+                validation: | validation_data: hdk::LinkValidationData| {
+                    match validation_data {
+                        // a game can only be put in the list of one of its own players
+                        hdk::LinkValidationData::LinkAdd{link, validation_data: _} => {
+                            let game = get_game(link.link.target())
+                                .map_err(|_| "Could not load the linked game during validation")?;
+                            match game.player_number(link.link.base()) {
+                                Some(_) => Ok(()),
+                                None => Err("A game can only be linked from one of its players".into()),
+                            }
+                        },
+                        hdk::LinkValidationData::LinkRemove{..} => {
+                            Err("Cannot remove a game from a player's list".into())
+                        }
+                    }
+                }
+            )
+        ]
     )
 }
@@ -1,7 +1,9 @@
 use hdk::{
+    AGENT_ADDRESS,
     entry_definition::ValidatingEntryType,
+    error::{ZomeApiResult, ZomeApiError},
     holochain_persistence_api::{
-        cas::content::{Address},
+        cas::content::{AddressableContent, Address},
     },
     holochain_json_api::{
         error::JsonError, json::JsonString,
@@ -14,14 +16,16 @@ use hdk::{
 };
 
 use crate::MoveType;
-use crate::game::{get_game_local_chain, get_state_local_chain};
+use crate::checkpoint;
+use crate::invitation;
+use crate::game::{self, Game, get_game, get_move, get_state_ending_at};
 
 
 #[derive(Clone, Debug, Serialize, Deserialize, DefaultJson)]
 pub struct MoveInput {
 	pub game: Address,
 	pub move_type: MoveType,
-	pub timestamp: u32,
+	pub timestamp: u64, // milliseconds since the unix epoch
 }
 
 
@@ -31,7 +35,127 @@ pub struct Move {
 	pub author: Address,
 	pub move_type: MoveType,
 	pub previous_move: Address,
-	pub timestamp: u32,
+	pub timestamp: u64, // milliseconds since the unix epoch
+	pub acceptance: Option<Acceptance>, // set on the first move only
+}
+
+/// Player 2's acceptance of the invitation to a game. The first move names it, and the clocks and
+/// the abandonment window start from when it was committed rather than from when the game was created.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Acceptance {
+	pub response: Address,
+	pub accepted_at: u64, // milliseconds since the unix epoch, from the acceptance's header
+}
+
+pub fn handle_make_move(new_move: MoveInput) -> ZomeApiResult<()> {
+    // get the current state from the DHT by following the hash chain
+    let game = get_game(&new_move.game)?;
+    let (state, checkpoint) = game::get_state_and_checkpoint(&new_move.game, &game)?;
+
+    // the new move follows the latest move, or the game itself if no moves have been made.
+    // The first move also names the acceptance that let the game start
+    let (base_address, acceptance) = match &state.last_move {
+        Some(last_move) => (Entry::App("move".into(), last_move.into()).address(), None),
+        None => match invitation::get_accepted_at(&new_move.game, &game)? {
+            Some(accepted_at) => {
+                let response = invitation::acceptance_address(&new_move.game, &game);
+                (new_move.game.clone(), Some(Acceptance{response, accepted_at}))
+            },
+            None => return Err(ZomeApiError::Internal("Player 2 has not accepted the invitation to this game yet".into())),
+        },
+    };
+
+    let new_move = Move {
+        game: new_move.game,
+        author: AGENT_ADDRESS.to_string().into(),
+        move_type: new_move.move_type,
+        previous_move: base_address.clone(),
+        timestamp: new_move.timestamp,
+        acceptance,
+    };
+    let move_entry = Entry::App(
+        "move".into(),
+        new_move.clone().into(),
+    );
+    let move_address = hdk::commit_entry(&move_entry)?;
+    hdk::link_entries(&base_address, &move_address, "", "")?;
+
+    // snapshot the state every so often so loading long games stays fast
+    let new_state = state.evolve(game, &new_move);
+    checkpoint::create_checkpoint_if_due(&new_move.game, &move_address, &new_state, checkpoint)?;
+    Ok(())
+}
+
+/// Reveal player 1's half of the coin flip deciding who goes first
+pub fn handle_reveal_coin_flip(game_address: Address, timestamp: u64) -> ZomeApiResult<()> {
+    let game = get_game(&game_address)?;
+    let secret = game::coin_flip_secret(&game.player_2, game.created_at)?;
+    handle_make_move(MoveInput {
+        game: game_address,
+        move_type: MoveType::CoinFlipReveal{secret},
+        timestamp,
+    })
+}
+
+/// The first move of a game must follow the game entry itself and every later move must follow
+/// another move of the same game, so moves can't be grafted onto an unrelated entry
+fn validate_previous_move(new_move: &Move) -> Result<(), String> {
+    if new_move.previous_move == new_move.game {
+        return Ok(())
+    }
+    let previous = get_move(&new_move.previous_move)
+        .map_err(|_| "previous_move must be the game or a move in the same game")?;
+    if previous.game != new_move.game {
+        return Err("previous_move belongs to a different game".into())
+    }
+    Ok(())
+}
+
+/// A game only starts once player 2 has accepted the invitation to play it. The first move names
+/// that acceptance so validating it depends on an entry that can be fetched, rather than on
+/// whether a response happens to be found yet. The time it names has to be when the acceptance
+/// was committed, as the clocks start from it.
+fn validate_acceptance(new_move: &Move, game: &Game) -> Result<(), String> {
+    if new_move.previous_move != new_move.game {
+        return match new_move.acceptance {
+            Some(_) => Err("Only the first move of a game names the acceptance of its invitation".into()),
+            None => Ok(()),
+        }
+    }
+    let expected = invitation::acceptance_address(&new_move.game, game);
+    match &new_move.acceptance {
+        Some(acceptance) if acceptance.response == expected => {
+            let accepted_at = invitation::get_accepted_at(&new_move.game, game)
+                .map_err(|_| "Could not load the acceptance of the invitation to this game during validation")?
+                .ok_or("Could not load the acceptance of the invitation to this game during validation")?;
+            if acceptance.accepted_at == accepted_at {
+                Ok(())
+            } else {
+                Err("The first move must give the time the invitation to this game was accepted".into())
+            }
+        },
+        _ => Err("The first move must name player 2's acceptance of the invitation to this game".into()),
+    }
+}
+
+/// Links between moves are how the move chain is traversed, so a link is only valid if it
+/// points from the entry the target move names as its previous_move. This stops moves being
+/// linked out of order or skipping over the moves in between.
+fn validate_move_link(validation_data: hdk::LinkValidationData) -> Result<(), String> {
+    match validation_data {
+        hdk::LinkValidationData::LinkAdd{link, validation_data: _} => {
+            let target = get_move(link.link.target())
+                .map_err(|_| "Could not load the linked move during validation")?;
+            if &target.previous_move == link.link.base() {
+                Ok(())
+            } else {
+                Err("A move can only be linked from its previous_move".into())
+            }
+        },
+        hdk::LinkValidationData::LinkRemove{..} => {
+            Err("Cannot remove a link between moves".into())
+        }
+    }
 }
 
 pub fn definition() -> ValidatingEntryType {
@@ -40,29 +164,33 @@ pub fn definition() -> ValidatingEntryType {
         description: "A move by an agent in an game",
         sharing: Sharing::Public,
         validation_package: || {
-            hdk::ValidationPackageDefinition::ChainFull
+            hdk::ValidationPackageDefinition::Entry
         },
 
         validation: | validation_data: hdk::EntryValidationData<Move>| {
             match validation_data {
                 EntryValidationData::Create{entry, validation_data} => {
-                	let mut local_chain = validation_data.package.source_chain_entries
-                		.ok_or("Could not retrieve source chain")?;
-                	hdk::debug(format!("{:?}", local_chain))?;
+                	let new_move = Move::from(entry);
 
-                	// load the game and game state
-                	let _new_move = Move::from(entry);
+                	// the agent committing the move must be the one it claims as author
+                	if !validation_data.sources().contains(&new_move.author) {
+                		return Err("Cannot author a move from another agent".into())
+                	}
+                	// clocks are measured with move timestamps, so they must be close to the real time
+                	game::validate_timestamp(new_move.timestamp, &validation_data)?;
 
-                    // Sometimes the validating entry is already in the chain when validation runs,
-                    // To make our state reduction work correctly this must be removed
-                    local_chain.remove_item(&Entry::App("move".into() , _new_move.clone().into()));
+                	validate_previous_move(&new_move)?;
 
-                	let state = get_state_local_chain(local_chain.clone(), &_new_move.game)
-                		.map_err(|_| "Could not load state during validation")?;
-                	let game = get_game_local_chain(local_chain, &_new_move.game)
+                	// load the game and the state before this move. Only this game's moves are
+                	// fetched, by following previous_move back to the game, rather than shipping
+                	// the author's whole source chain with every move
+                	let game = get_game(&new_move.game)
                 	    .map_err(|_| "Could not load game during validation")?;
-                    
-                    _new_move.is_valid(game, state)
+                	validate_acceptance(&new_move, &game)?;
+                	let state = get_state_ending_at(&new_move.game, &new_move.previous_move)
+                		.map_err(|_| "Could not load state during validation")?;
+
+                    new_move.is_valid(game, state)
                 },
                 _ => {
                     Err("Cannot modify or delete a move".into())
@@ -77,8 +205,8 @@ pub fn definition() -> ValidatingEntryType {
                 validation_package: || {
                     hdk::ValidationPackageDefinition::Entry
                 },
-                validation: | _validation_data: hdk::LinkValidationData| {
-                    Ok(())
+                validation: | validation_data: hdk::LinkValidationData| {
+                    validate_move_link(validation_data)
                 }
             ),
         	from!(
@@ -87,8 +215,8 @@ pub fn definition() -> ValidatingEntryType {
                 validation_package: || {
                     hdk::ValidationPackageDefinition::Entry
                 },
-                validation: | _validation_data: hdk::LinkValidationData| {
-                    Ok(())
+                validation: | validation_data: hdk::LinkValidationData| {
+                    validate_move_link(validation_data)
                 }
             )
         ]
@@ -0,0 +1,276 @@
+use hdk::{
+    AGENT_ADDRESS,
+    entry_definition::ValidatingEntryType,
+    error::{ZomeApiResult, ZomeApiError},
+    holochain_persistence_api::{
+        cas::content::{AddressableContent, Address},
+    },
+    holochain_json_api::{
+        error::JsonError, json::JsonString,
+    },
+    holochain_core_types::{
+        dna::entry_types::Sharing,
+        validation::EntryValidationData,
+        entry::Entry,
+        link::LinkMatch,
+    }
+};
+
+use holochain_wasm_utils::api_serialization::get_entry::{GetEntryOptions, GetEntryResultType};
+
+use crate::game::{self, Game, get_game};
+use crate::matchmaking::{self, GetResponse, ProposalChoice};
+
+/// Asks player 2 of a game to play it. Moves are only valid once the invitee has accepted.
+#[derive(Clone, Debug, Serialize, Deserialize, DefaultJson, PartialEq)]
+pub struct Invitation {
+    pub game: Address,
+    pub inviter: Address, // player 1
+    pub invitee: Address, // player 2
+}
+
+/// The invitee's answer to an invitation. It holds nothing but the answer so its address can be
+/// worked out by anyone who knows the invitation, which is how the first move of a game names it.
+#[derive(Clone, Debug, Serialize, Deserialize, DefaultJson, PartialEq)]
+pub struct InvitationResponse {
+    pub invitation: Address,
+    pub accepted: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum InvitationStatus {
+    Pending,
+    Accepted,
+    Declined,
+}
+
+impl Invitation {
+    pub fn for_game(game_address: &Address, game: &Game) -> Self {
+        Invitation {
+            game: game_address.clone(),
+            inviter: game.player_1.clone(),
+            invitee: game.player_2.clone(),
+        }
+    }
+
+    pub fn address(&self) -> Address {
+        Entry::App("invitation".into(), self.clone().into()).address()
+    }
+}
+
+pub fn response_address(invitation_addr: &Address, accepted: bool) -> Address {
+    Entry::App(
+        "invitation_response".into(),
+        InvitationResponse { invitation: invitation_addr.clone(), accepted }.into(),
+    ).address()
+}
+
+/// Commit the invitation for a newly created game and link it from the invitee so they can find it
+pub fn create_invitation(game_address: &Address, game: &Game) -> ZomeApiResult<Address> {
+    let invitation = Invitation::for_game(game_address, game);
+    let invitation_address = hdk::commit_entry(&Entry::App("invitation".into(), invitation.clone().into()))?;
+    hdk::link_entries(&invitation.invitee, &invitation_address, "has_invitation", "")?;
+    Ok(invitation_address)
+}
+
+/// Address player 2's acceptance of the invitation to a game has, once they have accepted it
+pub fn acceptance_address(game_address: &Address, game: &Game) -> Address {
+    response_address(&Invitation::for_game(game_address, game).address(), true)
+}
+
+/// When player 2 accepted a game, or None if they haven't yet. This is read from the header the
+/// acceptance was committed with, so it can't be misdated. If the acceptance was committed more
+/// than once the earliest commit counts.
+pub fn get_accepted_at(game_address: &Address, game: &Game) -> ZomeApiResult<Option<u64>> {
+    let options = GetEntryOptions { headers: true, ..Default::default() };
+    match hdk::get_entry_result(&acceptance_address(game_address, game), options)?.result {
+        GetEntryResultType::Single(item) => {
+            let times = item.headers.iter()
+                .map(|header| game::header_time_ms(header).map_err(ZomeApiError::Internal))
+                .collect::<ZomeApiResult<Vec<u64>>>()?;
+            Ok(times.into_iter().min())
+        },
+        _ => Ok(None),
+    }
+}
+
+pub fn get_status(invitation_addr: &Address) -> ZomeApiResult<InvitationStatus> {
+    if hdk::get_entry(&response_address(invitation_addr, true))?.is_some() {
+        Ok(InvitationStatus::Accepted)
+    } else if hdk::get_entry(&response_address(invitation_addr, false))?.is_some() {
+        Ok(InvitationStatus::Declined)
+    } else {
+        Ok(InvitationStatus::Pending)
+    }
+}
+
+fn is_answered(invitation_addr: &Address) -> ZomeApiResult<bool> {
+    Ok(get_status(invitation_addr)? != InvitationStatus::Pending)
+}
+
+/// List the invitations sent to the current agent that haven't been accepted or declined yet
+pub fn handle_get_invitations() -> ZomeApiResult<Vec<GetResponse<Invitation>>> {
+    let me: Address = AGENT_ADDRESS.to_string().into();
+    hdk::utils::get_links_and_load_type(&me, LinkMatch::Exactly("has_invitation"), LinkMatch::Any)?
+        .into_iter()
+        .map(|invitation: Invitation| GetResponse { address: invitation.address(), entry: invitation })
+        .filter_map(|response| match is_answered(&response.address) {
+            Ok(true) => None,
+            Ok(false) => Some(Ok(response)),
+            Err(e) => Some(Err(e)),
+        })
+        .collect()
+}
+
+pub fn handle_accept_invitation(invitation_addr: Address) -> ZomeApiResult<Address> {
+    respond(invitation_addr, true)
+}
+
+pub fn handle_decline_invitation(invitation_addr: Address) -> ZomeApiResult<Address> {
+    respond(invitation_addr, false)
+}
+
+fn respond(invitation_addr: Address, accepted: bool) -> ZomeApiResult<Address> {
+    let invitation: Invitation = hdk::utils::get_as_type(invitation_addr.clone())?;
+    if invitation.invitee.to_string() != AGENT_ADDRESS.to_string() {
+        return Err(ZomeApiError::Internal("Only the invited agent can answer an invitation".into()))
+    }
+    if is_answered(&invitation_addr)? {
+        return Err(ZomeApiError::Internal("This invitation has already been answered".into()))
+    }
+    let game = get_game(&invitation.game)?;
+    let proposal_responses = match (&game.proposal, accepted) {
+        (Some(proposal_addr), true) => matchmaking::handle_check_responses(proposal_addr.clone())?,
+        _ => Vec::new(),
+    };
+    // a proposer confirms exactly one of the games made from their proposal
+    if proposal_responses.iter().any(|response| response.status == InvitationStatus::Accepted) {
+        return Err(ZomeApiError::Internal("Another game made from this proposal has already been accepted".into()))
+    }
+    if proposal_responses.iter().any(|response| response.chosen && response.address != invitation.game) {
+        return Err(ZomeApiError::Internal("Another game made from this proposal has already been chosen".into()))
+    }
+    // the proposer's choice is recorded before the game is accepted, as accepting depends on it
+    if let (Some(proposal_addr), true) = (&game.proposal, accepted) {
+        let choice = ProposalChoice { proposal: proposal_addr.clone(), game: invitation.game.clone() };
+        hdk::commit_entry(&Entry::App("proposal_choice".into(), choice.into()))?;
+    }
+    let response = InvitationResponse { invitation: invitation_addr.clone(), accepted };
+    let response_address = hdk::commit_entry(&Entry::App("invitation_response".into(), response.into()))?;
+    // and every other game made from it is declined so both sides agree which one is being played
+    for other in proposal_responses {
+        let other_invitation = Invitation::for_game(&other.address, &other.entry).address();
+        if other_invitation != invitation_addr && other.status == InvitationStatus::Pending {
+            let decline = InvitationResponse { invitation: other_invitation, accepted: false };
+            hdk::commit_entry(&Entry::App("invitation_response".into(), decline.into()))?;
+        }
+    }
+    Ok(response_address)
+}
+
+pub fn invitation_def() -> ValidatingEntryType {
+    entry!(
+        name: "invitation",
+        description: "Asks an agent to play a game that has been created with them as player 2",
+        sharing: Sharing::Public,
+        validation_package: || {
+            hdk::ValidationPackageDefinition::Entry
+        },
+        validation: | validation_data: hdk::EntryValidationData<Invitation>| {
+            match validation_data {
+                EntryValidationData::Create{entry, validation_data} => {
+                    let invitation = Invitation::from(entry);
+                    if !validation_data.sources().contains(&invitation.inviter) {
+                        return Err("Cannot send an invitation from another agent".into())
+                    }
+                    let game = get_game(&invitation.game)
+                        .map_err(|_| "Could not load game during validation")?;
+                    if invitation == Invitation::for_game(&invitation.game, &game) {
+                        Ok(())
+                    } else {
+                        Err("An invitation must be from player 1 of the game to player 2".into())
+                    }
+                },
+                _ => {
+                    Err("Cannot modify or delete an invitation".into())
+                }
+            }
+        },
+        links: [
+            from!(
+                "%agent_id",
+                link_type: "has_invitation",
+                validation_package: || {
+                    hdk::ValidationPackageDefinition::Entry
+                },
+                validation: | validation_data: hdk::LinkValidationData| {
+                    match validation_data {
+                        hdk::LinkValidationData::LinkAdd{link, validation_data: _} => {
+                            let invitation: Invitation = hdk::utils::get_as_type(link.link.target().clone())
+                                .map_err(|_| "Could not load the linked invitation during validation")?;
+                            if &invitation.invitee == link.link.base() {
+                                Ok(())
+                            } else {
+                                Err("An invitation can only be linked from its invitee".into())
+                            }
+                        },
+                        hdk::LinkValidationData::LinkRemove{..} => {
+                            Err("Cannot remove a link to an invitation".into())
+                        }
+                    }
+                }
+            )
+        ]
+    )
+}
+
+pub fn invitation_response_def() -> ValidatingEntryType {
+    entry!(
+        name: "invitation_response",
+        description: "An invitee accepting or declining an invitation to a game",
+        sharing: Sharing::Public,
+        validation_package: || {
+            hdk::ValidationPackageDefinition::Entry
+        },
+        validation: | validation_data: hdk::EntryValidationData<InvitationResponse>| {
+            match validation_data {
+                EntryValidationData::Create{entry, validation_data} => {
+                    let response = InvitationResponse::from(entry);
+                    let invitation: Invitation = hdk::utils::get_as_type(response.invitation.clone())
+                        .map_err(|_| "Could not load invitation during validation")?;
+                    if !validation_data.sources().contains(&invitation.invitee) {
+                        return Err("Only the invited agent can answer an invitation".into())
+                    }
+                    // an invitation can be accepted or declined but not both
+                    let other_answer = hdk::get_entry(&response_address(&response.invitation, !response.accepted))
+                        .map_err(|_| "Could not check for an earlier answer during validation")?;
+                    if other_answer.is_some() {
+                        return Err("This invitation has already been answered".into())
+                    }
+                    if !response.accepted {
+                        return Ok(())
+                    }
+                    let game = get_game(&invitation.game)
+                        .map_err(|_| "Could not load game during validation")?;
+                    // a proposer can only accept a game they have chosen for the proposal
+                    match game.proposal {
+                        Some(proposal_addr) => {
+                            let chosen = matchmaking::is_chosen(&proposal_addr, &invitation.game)
+                                .map_err(|_| "Could not load the choice of game for the proposal during validation")?;
+                            if chosen {
+                                Ok(())
+                            } else {
+                                Err("The proposer has to choose this game for the proposal before accepting it".into())
+                            }
+                        },
+                        None => Ok(())
+                    }
+                },
+                _ => {
+                    Err("Cannot modify or delete an answer to an invitation".into())
+                }
+            }
+        },
+        links: []
+    )
+}
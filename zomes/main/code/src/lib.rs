@@ -1,10 +1,11 @@
-#![feature(try_from, vec_remove_item, proc_macro_hygiene)]
+#![feature(try_from, proc_macro_hygiene)]
 #[macro_use]
 extern crate hdk;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
+extern crate chrono;
 #[macro_use]
 extern crate holochain_json_derive;
 
@@ -16,10 +17,7 @@ use hdk::{
     entry_definition::ValidatingEntryType,
     error::ZomeApiResult,
     holochain_persistence_api::{
-        cas::content::{Address},
-    },
-    holochain_core_types::{
-        entry::Entry,
+        cas::content::Address,
     },
 };
 
@@ -34,11 +32,14 @@ pub use your_game::{
 
 mod game;
 mod game_move;
+mod checkpoint;
 mod matchmaking;
+mod invitation;
 
-use game::Game;
-use game_move::{Move, MoveInput};
-use matchmaking::{GameProposal, GetResponse};
+use game::{Game, Fork, GameSummary, GameSettings};
+use game_move::MoveInput;
+use matchmaking::{GameProposal, GetResponse, ProposalFilter, ProposalResponse};
+use invitation::Invitation;
 
 #[zome]
 pub mod main {
@@ -62,6 +63,11 @@ pub mod main {
         game_move::definition()
     }
 
+    #[entry_def]
+    fn checkpoint_entry_def() -> ValidatingEntryType {
+        checkpoint::definition()
+    }
+
     #[entry_def]
     fn game_proposal_def() -> ValidatingEntryType {
         matchmaking::game_proposal_def()
@@ -72,6 +78,21 @@ pub mod main {
         matchmaking::anchor_def()
     }
 
+    #[entry_def]
+    fn proposal_choice_def() -> ValidatingEntryType {
+        matchmaking::proposal_choice_def()
+    }
+
+    #[entry_def]
+    fn invitation_def() -> ValidatingEntryType {
+        invitation::invitation_def()
+    }
+
+    #[entry_def]
+    fn invitation_response_def() -> ValidatingEntryType {
+        invitation::invitation_response_def()
+    }
+
     /*=====  End of Entry Definitions  ======*/
 
 
@@ -80,51 +101,26 @@ pub mod main {
     ======================================*/
 
     #[zome_fn("hc_public")]
-    fn create_game(opponent: Address, timestamp: u32) -> ZomeApiResult<Address> {
+    fn create_game(opponent: Address, timestamp: u64, settings: GameSettings) -> ZomeApiResult<Address> {
         let new_game = Game {
             player_1: AGENT_ADDRESS.to_string().into(),
+            coin_flip_commitment: game::new_coin_flip_commitment(&settings.first_player, &opponent, timestamp)?,
             player_2: opponent,
             created_at: timestamp,
+            settings,
+            proposal: None,
         };
-        let game_entry = Entry::App(
-            "game".into(),
-            new_game.into(),
-        );
-        hdk::commit_entry(&game_entry)
+        game::create_game(new_game)
     }
 
     #[zome_fn("hc_public")]
     fn make_move(new_move: MoveInput) -> ZomeApiResult<()> {
-        // get all the moves from the DHT by following the hash chain
-        let moves = game::get_moves(&new_move.game)?;
-
-        // commit the latest move to local chain to allow validation of the next move (if one exists)
-        let base_address = match moves.last() {
-            Some(last_move) => {
-                let new_move_entry = Entry::App("move".into(), last_move.into());
-                hdk::commit_entry(&new_move_entry)?
-            }
-            None => { // no moves have been made so commit the Game
-                let game = game::get_game(&new_move.game)?;
-                let game_entry = Entry::App("game".into(), game.into());
-                hdk::commit_entry(&game_entry)?
-            }
-        };
+        game_move::handle_make_move(new_move)
+    }
 
-        let new_move = Move {
-            game: new_move.game,
-            author: AGENT_ADDRESS.to_string().into(),
-            move_type: new_move.move_type,
-            previous_move: base_address.clone(),
-            timestamp: new_move.timestamp,
-        };
-        let move_entry = Entry::App(
-            "move".into(),
-            new_move.into(),
-        );
-        let move_address = hdk::commit_entry(&move_entry)?;
-        hdk::link_entries(&base_address, &move_address, "", "")?;
-        Ok(())
+    #[zome_fn("hc_public")]
+    fn reveal_coin_flip(game_address: Address, timestamp: u64) -> ZomeApiResult<()> {
+        game_move::handle_reveal_coin_flip(game_address, timestamp)
     }
 
     #[zome_fn("hc_public")]
@@ -137,6 +133,16 @@ pub mod main {
         Ok(game::get_state(&game_address)?.render())
     }
 
+    #[zome_fn("hc_public")]
+    fn get_my_games() -> ZomeApiResult<Vec<GameSummary>> {
+        game::get_my_games()
+    }
+
+    #[zome_fn("hc_public")]
+    fn get_forks(game_address: Address) -> ZomeApiResult<Vec<Fork>> {
+        game::get_forks(&game_address)
+    }
+
     #[zome_fn("hc_public")]
     fn get_valid_moves() -> ZomeApiResult<Vec<MoveType>> {
         Ok(MoveType::describe())
@@ -149,22 +155,22 @@ pub mod main {
 
 
     #[zome_fn("hc_public")]
-    fn create_proposal(message: String) -> ZomeApiResult<Address> {
-        matchmaking::handle_create_proposal(message)
+    fn create_proposal(message: String, settings: GameSettings, expires_at: u64) -> ZomeApiResult<Address> {
+        matchmaking::handle_create_proposal(message, settings, expires_at)
     }
 
     #[zome_fn("hc_public")]
-    fn get_proposals() -> ZomeApiResult<Vec<GetResponse<GameProposal>>> {
-        matchmaking::handle_get_proposals()
+    fn get_proposals(filter: Option<ProposalFilter>, now: u64) -> ZomeApiResult<Vec<GetResponse<GameProposal>>> {
+        matchmaking::handle_get_proposals(filter, now)
     }
 
     #[zome_fn("hc_public")]
-    fn accept_proposal(proposal_addr: Address, created_at: u32) -> ZomeApiResult<Address> {
+    fn accept_proposal(proposal_addr: Address, created_at: u64) -> ZomeApiResult<Address> {
         matchmaking::handle_accept_proposal(proposal_addr, created_at)
     }
 
     #[zome_fn("hc_public")]
-    fn check_responses(proposal_addr: Address) -> ZomeApiResult<Vec<GetResponse<Game>>> {
+    fn check_responses(proposal_addr: Address) -> ZomeApiResult<Vec<ProposalResponse>> {
         matchmaking::handle_check_responses(proposal_addr)
     }
 
@@ -172,5 +178,20 @@ pub mod main {
     fn remove_proposal(proposal_addr: Address) -> ZomeApiResult<Address> {
         matchmaking::handle_remove_proposal(proposal_addr)
     }
+
+    #[zome_fn("hc_public")]
+    fn get_invitations() -> ZomeApiResult<Vec<GetResponse<Invitation>>> {
+        invitation::handle_get_invitations()
+    }
+
+    #[zome_fn("hc_public")]
+    fn accept_invitation(invitation_addr: Address) -> ZomeApiResult<Address> {
+        invitation::handle_accept_invitation(invitation_addr)
+    }
+
+    #[zome_fn("hc_public")]
+    fn decline_invitation(invitation_addr: Address) -> ZomeApiResult<Address> {
+        invitation::handle_decline_invitation(invitation_addr)
+    }
     /*=====  End of Zome functions  ======*/
 }
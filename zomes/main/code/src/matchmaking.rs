@@ -1,7 +1,7 @@
 use hdk::{
     AGENT_ADDRESS,
     entry_definition::ValidatingEntryType,
-    error::ZomeApiResult,
+    error::{ZomeApiResult, ZomeApiError},
     holochain_persistence_api::{
         cas::content::{AddressableContent, Address},
     },
@@ -19,12 +19,59 @@ use hdk::{
 use serde::Serialize;
 use std::fmt::Debug;
 
-use crate::game::Game;
+use crate::game::{self, Game, GameSettings, RuleVariant};
+use crate::invitation::{self, Invitation, InvitationStatus};
 
 #[derive(Serialize, Deserialize, Debug, DefaultJson, Clone)]
 pub struct GameProposal {
     pub agent: Address,
     pub message: String,
+    pub settings: GameSettings, // copied into the game when the proposal is accepted
+    pub expires_at: u64, // milliseconds since the unix epoch, the proposal can't be accepted from then on
+}
+
+impl GameProposal {
+    pub fn is_expired(&self, now: u64) -> bool {
+        now >= self.expires_at
+    }
+}
+
+/// Narrows down the proposals returned by get_proposals. Each field left as None matches anything.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ProposalFilter {
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub timed: Option<bool>,
+    pub variant: Option<RuleVariant>,
+    pub rated: Option<bool>,
+}
+
+impl ProposalFilter {
+    pub fn matches(&self, settings: &GameSettings) -> bool {
+        self.width.map_or(true, |width| width == settings.width)
+            && self.height.map_or(true, |height| height == settings.height)
+            && self.timed.map_or(true, |timed| timed == settings.time_control.is_some())
+            && self.variant.as_ref().map_or(true, |variant| variant == &settings.variant)
+            && self.rated.map_or(true, |rated| rated == settings.rated)
+    }
+}
+
+/// A game made from a proposal, and whether the proposer has accepted or declined it
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone)]
+pub struct ProposalResponse {
+    pub address: Address,
+    pub entry: Game,
+    pub status: InvitationStatus,
+    pub chosen: bool, // whether this is the game the proposer chose to play
+}
+
+/// The proposer's pick of which game made from their proposal they will play. It is committed just
+/// before they accept that game's invitation. Its address is worked out from the proposal and the
+/// game, so validating the acceptance only has to fetch it.
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone)]
+pub struct ProposalChoice {
+    pub proposal: Address,
+    pub game: Address,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -39,12 +86,14 @@ impl<T: Into<JsonString> + Debug + Serialize> From<GetResponse<T>> for JsonStrin
     }
 } 
 
-pub fn handle_create_proposal(message: String) -> ZomeApiResult<Address> {
+pub fn handle_create_proposal(message: String, settings: GameSettings, expires_at: u64) -> ZomeApiResult<Address> {
 
     // create the data as a struct
     let game_proposal_data = GameProposal { 
         agent: AGENT_ADDRESS.to_string().into(),
         message,
+        settings,
+        expires_at,
     };
     
     // create an entry
@@ -76,40 +125,62 @@ pub fn handle_create_proposal(message: String) -> ZomeApiResult<Address> {
     Ok(proposal_address)
 }
 
-pub fn handle_get_proposals() -> ZomeApiResult<Vec<GetResponse<GameProposal>>> {
+/// List the proposals that can still be accepted at `now`. Expired proposals and ones that have
+/// already been accepted are left out, along with any that don't match the filter.
+pub fn handle_get_proposals(filter: Option<ProposalFilter>, now: u64) -> ZomeApiResult<Vec<GetResponse<GameProposal>>> {
+    let filter = filter.unwrap_or_default();
     // define the anchor entry again and compute its hash
     let anchor_address = Entry::App(
         "anchor".into(),
         "game_proposals".into()
     ).address();
     
-    Ok(
-        hdk::utils::get_links_and_load_type(
-            &anchor_address, 
-            LinkMatch::Exactly("has_proposal"), // the link type to match
-            LinkMatch::Any
-        )?.into_iter().map(|proposal: GameProposal| {
-            let address = Entry::App("game_proposal".into(), proposal.clone().into()).address();
-            GetResponse{entry: proposal, address}
-        }).collect()
-    )
+    let mut open_proposals = Vec::new();
+    for proposal in hdk::utils::get_links_and_load_type::<GameProposal>(
+        &anchor_address, 
+        LinkMatch::Exactly("has_proposal"), // the link type to match
+        LinkMatch::Any
+    )? {
+        if proposal.is_expired(now) || !filter.matches(&proposal.settings) {
+            continue;
+        }
+        let address = Entry::App("game_proposal".into(), proposal.clone().into()).address();
+        if is_accepted(&address)? {
+            continue;
+        }
+        open_proposals.push(GetResponse{entry: proposal, address});
+    }
+    Ok(open_proposals)
+}
+
+/// A proposal has been accepted once the proposer has accepted one of the games made from it.
+/// Until then anyone else can still make a game from it and hope to be picked.
+fn is_accepted(proposal_addr: &Address) -> ZomeApiResult<bool> {
+    Ok(handle_check_responses(proposal_addr.clone())?
+        .iter()
+        .any(|response| response.status == InvitationStatus::Accepted))
 }
 
-pub fn handle_accept_proposal(proposal_addr: Address, created_at: u32) -> ZomeApiResult<Address> {
+pub fn handle_accept_proposal(proposal_addr: Address, created_at: u64) -> ZomeApiResult<Address> {
     // this will early return error if it doesn't exist
     let proposal: GameProposal = hdk::utils::get_as_type(proposal_addr.clone())?;
+    if proposal.is_expired(created_at) {
+        return Err(ZomeApiError::Internal("This proposal has expired".into()))
+    }
+    if is_accepted(&proposal_addr)? {
+        return Err(ZomeApiError::Internal("The proposer has already accepted another game for this proposal".into()))
+    }
 
-    // create the new game
+    // create the new game with the settings the proposer advertised
     let game = Game {
         player_1: AGENT_ADDRESS.to_string().into(),
+        coin_flip_commitment: game::new_coin_flip_commitment(&proposal.settings.first_player, &proposal.agent, created_at)?,
         player_2: proposal.agent,
         created_at,
+        settings: proposal.settings,
+        proposal: Some(proposal_addr.clone()),
     };
-    let game_entry = Entry::App(
-        "game".into(),
-        game.into()
-    );
-    let game_addr = hdk::commit_entry(&game_entry)?;
+    let game_addr = game::create_game(game)?;
 
     // link to the proposal
     hdk::link_entries(
@@ -121,18 +192,52 @@ pub fn handle_accept_proposal(proposal_addr: Address, created_at: u32) -> ZomeAp
     Ok(game_addr)
 }
 
-pub fn handle_check_responses(proposal_addr: Address) -> ZomeApiResult<Vec<GetResponse<Game>>> {
-    Ok(
-        hdk::utils::get_links_and_load_type(&proposal_addr, LinkMatch::Exactly("from_proposal".into()), LinkMatch::Any)?
-        .into_iter().map(|game: Game| {
+pub fn choice_address(proposal_addr: &Address, game_addr: &Address) -> Address {
+    Entry::App(
+        "proposal_choice".into(),
+        ProposalChoice { proposal: proposal_addr.clone(), game: game_addr.clone() }.into(),
+    ).address()
+}
+
+/// Whether the proposer has chosen to play this game made from their proposal
+pub fn is_chosen(proposal_addr: &Address, game_addr: &Address) -> ZomeApiResult<bool> {
+    Ok(hdk::get_entry(&choice_address(proposal_addr, game_addr))?.is_some())
+}
+
+/// List the games made from a proposal. The proposer accepts one of them with accept_invitation,
+/// which declines all the others.
+pub fn handle_check_responses(proposal_addr: Address) -> ZomeApiResult<Vec<ProposalResponse>> {
+    let responses = hdk::utils::get_links_and_load_type(&proposal_addr, LinkMatch::Exactly("from_proposal".into()), LinkMatch::Any)?
+        .into_iter().map(|game: Game| -> ZomeApiResult<ProposalResponse> {
             let address = Entry::App("game".into(), game.clone().into()).address();
-            GetResponse{entry: game, address}
-        }).collect()
-    )
+            let status = invitation::get_status(&Invitation::for_game(&address, &game).address())?;
+            let chosen = is_chosen(&proposal_addr, &address)?;
+            Ok(ProposalResponse{address, entry: game, status, chosen})
+        }).collect::<ZomeApiResult<Vec<ProposalResponse>>>()?;
+    // once the proposer has chosen a game every other one is declined, including any made since
+    let any_chosen = responses.iter().any(|response| response.chosen);
+    Ok(responses.into_iter().map(|mut response| {
+        if any_chosen && !response.chosen {
+            response.status = InvitationStatus::Declined;
+        }
+        response
+    }).collect())
 }
 
+/// Withdraw a proposal by unlinking it from the anchor, so it no longer shows up in get_proposals.
+/// The proposal itself is kept, as games already made from it are validated against it. Only the
+/// agent who made the proposal can remove it.
 pub fn handle_remove_proposal(proposal_addr: Address) -> ZomeApiResult<Address> {
-    hdk::remove_entry(&proposal_addr)
+    let proposal: GameProposal = hdk::utils::get_as_type(proposal_addr.clone())?;
+    if proposal.agent.to_string() != AGENT_ADDRESS.to_string() {
+        return Err(ZomeApiError::Internal("Only the agent who made a proposal can remove it".into()))
+    }
+    let anchor_address = Entry::App(
+        "anchor".into(),
+        "game_proposals".into()
+    ).address();
+    hdk::remove_link(&anchor_address, &proposal_addr, "has_proposal", "")?;
+    Ok(proposal_addr)
 }
 
 pub fn game_proposal_def() -> ValidatingEntryType {
@@ -152,18 +257,14 @@ pub fn game_proposal_def() -> ValidatingEntryType {
                 // only match if the entry is being created (not modified or deleted)
                 EntryValidationData::Create{ entry, validation_data } => {
                     let game_proposal = GameProposal::from(entry);
-                    if validation_data.sources().contains(&game_proposal.agent) {
-                        Ok(())
-                    } else {
-                        Err("Cannot author a proposal from another agent".into())
+                    if !validation_data.sources().contains(&game_proposal.agent) {
+                        return Err("Cannot author a proposal from another agent".into())
                     }
-                    
-                },
-                EntryValidationData::Delete{..} => { // should update to only the author can delete
-                    Ok(())
+                    game_proposal.settings.validate()
                 },
+                // games made from a proposal are validated against it, so it can only be unlinked
                 _ => {
-                    Err("Cannot modify, only create and delete".into())
+                    Err("Cannot modify or delete a proposal".into())
                 }
             }
         },
@@ -172,8 +273,22 @@ pub fn game_proposal_def() -> ValidatingEntryType {
                 "game",
                 link_type: "from_proposal",
                 validation_package: || { hdk::ValidationPackageDefinition::Entry },
-                validation: | _validation_data: hdk::LinkValidationData| {
-                    Ok(())
+                validation: | validation_data: hdk::LinkValidationData| {
+                    match validation_data {
+                        // only games that name the proposal can be listed as responses to it
+                        hdk::LinkValidationData::LinkAdd{link, validation_data: _} => {
+                            let game: Game = hdk::utils::get_as_type(link.link.target().clone())
+                                .map_err(|_| "Could not load the linked game during validation")?;
+                            if game.proposal.as_ref() == Some(link.link.base()) {
+                                Ok(())
+                            } else {
+                                Err("Only a game made from this proposal can be linked from it".into())
+                            }
+                        },
+                        hdk::LinkValidationData::LinkRemove{..} => {
+                            Err("Cannot remove a response to a proposal".into())
+                        }
+                    }
                 }
             )
         ]
@@ -196,10 +311,56 @@ pub fn anchor_def() -> ValidatingEntryType {
                 validation_package: || {
                     hdk::ValidationPackageDefinition::Entry
                 },
-                validation: | _validation_data: hdk::LinkValidationData| {
-                    Ok(())
+                validation: | validation_data: hdk::LinkValidationData| {
+                    match validation_data {
+                        hdk::LinkValidationData::LinkAdd{..} => {
+                            Ok(())
+                        },
+                        // withdrawing a proposal is up to the agent who made it
+                        hdk::LinkValidationData::LinkRemove{link, validation_data} => {
+                            let game_proposal: GameProposal = hdk::utils::get_as_type(link.link.target().clone())
+                                .map_err(|_| "Could not load the linked proposal during validation")?;
+                            if validation_data.sources().contains(&game_proposal.agent) {
+                                Ok(())
+                            } else {
+                                Err("Only the agent who made a proposal can unlink it".into())
+                            }
+                        }
+                    }
                 }
             )
         ]
     )
-}
\ No newline at end of file
+}
+
+pub fn proposal_choice_def() -> ValidatingEntryType {
+    entry!(
+        name: "proposal_choice",
+        description: "The game made from a proposal that the proposer has chosen to play",
+        sharing: Sharing::Public,
+        validation_package: || { hdk::ValidationPackageDefinition::Entry },
+        validation: | validation_data: hdk::EntryValidationData<ProposalChoice>| {
+            match validation_data {
+                EntryValidationData::Create{ entry, validation_data } => {
+                    let choice = ProposalChoice::from(entry);
+                    let game_proposal: GameProposal = hdk::utils::get_as_type(choice.proposal.clone())
+                        .map_err(|_| "Could not load the proposal during validation")?;
+                    if !validation_data.sources().contains(&game_proposal.agent) {
+                        return Err("Only the agent who made a proposal can choose a game for it".into())
+                    }
+                    let game: Game = hdk::utils::get_as_type(choice.game.clone())
+                        .map_err(|_| "Could not load the chosen game during validation")?;
+                    if game.proposal.as_ref() == Some(&choice.proposal) {
+                        Ok(())
+                    } else {
+                        Err("Only a game made from this proposal can be chosen for it".into())
+                    }
+                },
+                _ => {
+                    Err("Cannot modify or delete the game chosen for a proposal".into())
+                }
+            }
+        },
+        links: []
+    )
+}
@@ -13,16 +13,37 @@ use crate::your_game::state::LineDirection;
  *
  */
 
-//<<DEVCAMP-TODO>>: Add in "Resign" as a MoveType
-
 #[derive(Clone, Debug, Serialize, Deserialize, DefaultJson, PartialEq)]
 pub enum MoveType {
-    Place {x: usize, y: usize, direction: LineDirection }
+    Place {x: usize, y: usize, direction: LineDirection },
+    Resign, // Either player can give up at any time and the other player wins
+    ClaimTimeout, // In a timed game, win because the opponent's clock has run out
+    ClaimAbandonment, // Win because the opponent hasn't moved within the game's inactivity window
+    OfferDraw, // Propose ending the game as a draw, can be made at any time
+    AcceptDraw, // Accept the opponent's draw offer, ending the game
+    DeclineDraw, // Turn down the opponent's draw offer and keep playing
+    RequestUndo, // Ask the opponent to take back the last line placed
+    ApproveUndo, // Agree to the opponent's request to take back the last line
+    CoinFlipSeed {seed: String}, // Player 2's half of the coin flip deciding who goes first
+    CoinFlipReveal {secret: String}, // Player 1's secret matching the commitment in the game, settles the coin flip
+    ClaimCoinFlip, // Player 2 goes first because player 1 didn't reveal the coin flip in time
 }
 
 impl MoveType {
 	pub fn describe() -> Vec<MoveType> {
-		// <<DEVCAMP-TODO>> SHOULD RETURN AN EXAMPLE OF EACH VARIANT
-		vec![MoveType::Place{x:0,y:0,direction:LineDirection::Up}]
+		vec![
+			MoveType::Place{x:0,y:0,direction:LineDirection::Up},
+			MoveType::Resign,
+			MoveType::ClaimTimeout,
+			MoveType::ClaimAbandonment,
+			MoveType::OfferDraw,
+			MoveType::AcceptDraw,
+			MoveType::DeclineDraw,
+			MoveType::RequestUndo,
+			MoveType::ApproveUndo,
+			MoveType::CoinFlipSeed{seed:"any string".into()},
+			MoveType::CoinFlipReveal{secret:"secret from reveal_coin_flip".into()},
+			MoveType::ClaimCoinFlip,
+		]
 	}
 }
@@ -3,7 +3,7 @@ use hdk::holochain_json_api::{
 };
 
 use crate::game_move::Move;
-use crate::game::Game;
+use crate::game::{self, Game, FirstPlayer, RuleVariant};
 use super::MoveType;
 
 /**
@@ -39,17 +39,36 @@ fn digit_to_char(number: &usize) -> char {
     }
 }
 
+//Minutes and seconds left on a clock, e.g. 4:05
+fn format_clock(ms: u64) -> String {
+    format!("{}:{:02}", ms / 60000, (ms / 1000) % 60)
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, DefaultJson, PartialEq)]
 pub enum GameStatus {
     Ready,
 	InProgress,
     Surrendered, //Player has resigned
     Completed,
+    TimedOut, //Player ran out of time on their clock
+    Abandoned, //Player stopped moving and their opponent claimed the game
+    DrawAgreed, //Both players agreed to end the game as a draw
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, DefaultJson, PartialEq)]
+pub enum GameResult {
+    None, //Game is still being played
+    Player1Wins,
+    Player2Wins,
+    Draw, //Both players completed the same number of boxes
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, DefaultJson)]
 pub struct GameState {
+    //Moves made since the checkpoint this state was loaded from, or every move if there wasn't one
     pub moves: Vec<Move>,
+    pub move_count: usize, //Number of moves made in the whole game
+    pub last_move: Option<Move>, //Latest move, kept even when `moves` starts after a checkpoint
     // Implement your own game state
     // May be helpful to split this into state for each player, but I think I just need the lines
     pub lines: Vec<Line>,
@@ -58,7 +77,42 @@ pub struct GameState {
     pub player1_boxes: usize,
     pub player2_boxes: usize,
     pub status: GameStatus,
+    pub result: GameResult,
     pub next_player: usize,
+    pub resigned_player: Option<usize>, //Set to 1 or 2 once a player has resigned
+    pub draw_offered_by: Option<usize>, //Player with a draw offer waiting on their opponent
+    pub undo_requested_by: Option<usize>, //Player asking for the last line to be taken back
+    pub coin_flip: Option<CoinFlipStage>, //Set until a random first player has been decided
+    //Owner (1 or 2) of each box indexed [x][y] by its lower left grid point. 0 means not completed yet.
+    pub box_owners: Vec<Vec<usize>>,
+    //Bitset copy of `lines` for fast lookups. It is not serialized, `lines` is what JSON consumers see.
+    #[serde(skip)]
+    pub line_set: LineSet,
+    //Number of grid points along each axis, copied from the Game so the state can be rendered on its own
+    pub width: usize,
+    pub height: usize,
+    //Time left on each player's clock as of last_move_at. None for untimed games.
+    pub player1_time_ms: Option<u64>,
+    pub player2_time_ms: Option<u64>,
+    pub last_move_at: u64, //Timestamp the next player's clock has been running from, the acceptance for the first move
+    //Clocks as they stood before each line in `lines` was placed, so taking a line back restores them
+    pub clocks_before_lines: Vec<Clocks>,
+    //Player (1 or 2) who placed each line in `lines`, so taking a line back knows whose turn it was
+    pub line_players: Vec<usize>,
+}
+
+//Time left on both clocks at one point in the game
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Clocks {
+    pub player1_time_ms: Option<u64>,
+    pub player2_time_ms: Option<u64>,
+}
+
+//Progress of the coin flip for a game whose first player is Random
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum CoinFlipStage {
+    AwaitingSeed, //Player 2 has to pick a seed
+    AwaitingReveal {seed: String}, //Player 1 has to reveal the secret they committed to
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, DefaultJson, PartialEq)]
@@ -75,6 +129,86 @@ pub struct Line {
     pub direction: LineDirection,
 }
 
+//Fixed size bitsets of the horizontal and vertical lines on a board. Checking for a line is a single
+//bit test instead of searching the whole list of lines, which matters on big boards since every move
+//probes up to six lines during both validation and state evolution.
+#[derive(Clone, Debug, Default)]
+pub struct LineSet {
+    width: usize,
+    height: usize,
+    horizontal: Vec<u64>,
+    vertical: Vec<u64>,
+}
+
+const BITS_PER_WORD: usize = 64;
+
+impl LineSet {
+    pub fn new(width: usize, height: usize) -> Self {
+        //There are (width-1)*height horizontal lines and width*(height-1) vertical lines
+        let words = |bits: usize| (bits + BITS_PER_WORD - 1) / BITS_PER_WORD;
+        LineSet {
+            width,
+            height,
+            horizontal: vec![0; words((width - 1) * height)],
+            vertical: vec![0; words(width * (height - 1))],
+        }
+    }
+
+    //Position of a line in its bitset, or None if the line is off the board
+    fn index(&self, line: &Line) -> Option<usize> {
+        match line.direction {
+            LineDirection::Right => {
+                if line.x + 1 < self.width && line.y < self.height {
+                    Some(line.y * (self.width - 1) + line.x)
+                } else {
+                    None
+                }
+            },
+            LineDirection::Up => {
+                if line.x < self.width && line.y + 1 < self.height {
+                    Some(line.y * self.width + line.x)
+                } else {
+                    None
+                }
+            },
+        }
+    }
+
+    fn bits(&self, direction: &LineDirection) -> &Vec<u64> {
+        match direction {
+            LineDirection::Right => &self.horizontal,
+            LineDirection::Up => &self.vertical,
+        }
+    }
+
+    pub fn contains(&self, line: &Line) -> bool {
+        match self.index(line) {
+            Some(i) => self.bits(&line.direction)[i / BITS_PER_WORD] & (1 << (i % BITS_PER_WORD)) != 0,
+            None => false,
+        }
+    }
+
+    pub fn insert(&mut self, line: &Line) {
+        if let Some(i) = self.index(line) {
+            let bits = match line.direction {
+                LineDirection::Right => &mut self.horizontal,
+                LineDirection::Up => &mut self.vertical,
+            };
+            bits[i / BITS_PER_WORD] |= 1 << (i % BITS_PER_WORD);
+        }
+    }
+
+    pub fn remove(&mut self, line: &Line) {
+        if let Some(i) = self.index(line) {
+            let bits = match line.direction {
+                LineDirection::Right => &mut self.horizontal,
+                LineDirection::Up => &mut self.vertical,
+            };
+            bits[i / BITS_PER_WORD] &= !(1 << (i % BITS_PER_WORD));
+        }
+    }
+}
+
 //Built-in Rust trait to implement an equality determination 
 /* impl PartialEq for Piece {
     fn eq(&self, other: &Self)-> bool {
@@ -82,33 +216,35 @@ pub struct Line {
     }
 } */
 
-// <<DEVCAMP-TODO>> Can extend BOARD_SIZE in the future to be specified when a new game is created
-const BOARD_SIZE: usize = 3;
-//How do you specific exponents?
-const MAX_BOXES: usize = (BOARD_SIZE - 1) * (BOARD_SIZE - 1);
+//Limits on the number of grid points along each axis of a board. The upper bound keeps the axis
+//labels to a single digit when rendering in the CLI.
+pub const MIN_BOARD_SIZE: usize = 2;
+pub const MAX_BOARD_SIZE: usize = 10;
 
 impl Line {
-	pub fn is_in_bounds(&self) -> Result<(), String> {
+	pub fn is_in_bounds(&self, game_state: &GameState) -> Result<(), String> {
         match self.direction {
             LineDirection::Up => {
-                if self.x < BOARD_SIZE && self.y < (BOARD_SIZE - 1) {
+                if self.x < game_state.width && self.y < (game_state.height - 1) {
                     Ok(())
                 } else {
-                    Err("Vertical Line is not in bounds: Y must be between 0 and N-1".into())
+                    Err(format!("Vertical Line is not in bounds: X must be between 0 and {} and Y between 0 and {}",
+                        game_state.width - 1, game_state.height - 2))
                 }
             },
             LineDirection::Right => {
-                if self.x < (BOARD_SIZE - 1) && self.y < BOARD_SIZE {
+                if self.x < (game_state.width - 1) && self.y < game_state.height {
                     Ok(())
                 } else {
-                    Err("Horizontal Line is not in bounds: X must be between 0 and N-1".into())
+                    Err(format!("Horizontal Line is not in bounds: X must be between 0 and {} and Y between 0 and {}",
+                        game_state.width - 2, game_state.height - 1))
                 }
             },
         }
     }
 
     pub fn is_empty(&self, game_state: &GameState) -> Result<(), String> {
-        if game_state.lines.contains(self) {
+        if game_state.line_set.contains(self) {
             Err("Location is not empty".into())
         } else {
             Ok(())
@@ -122,63 +258,150 @@ impl Line {
 *       
 *   B) If new line is Vertical, Check if box will be completed by checking that line segments 
 *       exist for: H:x-1,y; H:x-1,y+1; H:x,y; H:x,y+1; V:x-1,y; and V:x+1,y1  */
-    pub fn is_part_of_box(&self, game_state: &GameState) -> usize {
-        let mut num_boxes = 0;
+    //Returns the lower left corner of each box the line would complete
+    pub fn completed_boxes(&self, game_state: &GameState) -> Vec<(usize, usize)> {
+        let mut boxes = Vec::new();
         match self.direction {
             LineDirection::Right => {
                 //Look to see if completes top or bottom box
-                if self.y < (BOARD_SIZE - 1) {
+                if self.y < (game_state.height - 1) {
                     let l1 = Line {x: self.x, y: self.y, direction:LineDirection::Up};
                     let l2 = Line {x: self.x, y: self.y + 1, direction:LineDirection::Right};
                     let l3 = Line {x: self.x + 1, y: self.y, direction:LineDirection::Up};
-                    if game_state.lines.contains(&l1) && 
-                    game_state.lines.contains(&l2) &&
-                    game_state.lines.contains(&l3) {num_boxes = num_boxes + 1;}
+                    if game_state.line_set.contains(&l1) && 
+                    game_state.line_set.contains(&l2) &&
+                    game_state.line_set.contains(&l3) {boxes.push((self.x, self.y));}
                 }
                 if self.y > 0 {
                     let l4 = Line {x: self.x + 1, y: self.y - 1, direction:LineDirection::Up};
                     let l5 = Line {x: self.x, y: self.y - 1, direction:LineDirection::Right};
                     let l6 = Line {x: self.x, y: self.y - 1, direction:LineDirection::Up};
-                    if game_state.lines.contains(&l4) && 
-                    game_state.lines.contains(&l5) &&
-                    game_state.lines.contains(&l6) {num_boxes = num_boxes + 1;}     
+                    if game_state.line_set.contains(&l4) && 
+                    game_state.line_set.contains(&l5) &&
+                    game_state.line_set.contains(&l6) {boxes.push((self.x, self.y - 1));}
                 }           
-                num_boxes
+                boxes
             }
             LineDirection::Up => {
                 //Look to see if completes left or right box
-                if self.x < (BOARD_SIZE - 1) {
+                if self.x < (game_state.width - 1) {
                     let l1 = Line {x: self.x, y: self.y + 1, direction:LineDirection::Right};
                     let l2 = Line {x: self.x + 1, y: self.y, direction:LineDirection::Up};
                     let l3 = Line {x: self.x, y: self.y, direction:LineDirection::Right};
-                    if game_state.lines.contains(&l1) && 
-                    game_state.lines.contains(&l2) &&
-                    game_state.lines.contains(&l3) {num_boxes = num_boxes + 1;}
+                    if game_state.line_set.contains(&l1) && 
+                    game_state.line_set.contains(&l2) &&
+                    game_state.line_set.contains(&l3) {boxes.push((self.x, self.y));}
                 }
                 if self.x > 0 {
                     let l4 = Line {x: self.x - 1, y: self.y, direction:LineDirection::Right};
                     let l5 = Line {x: self.x - 1, y: self.y, direction:LineDirection::Up};
                     let l6 = Line {x: self.x - 1, y: self.y + 1, direction:LineDirection::Right};
-                    if game_state.lines.contains(&l4) && 
-                    game_state.lines.contains(&l5) &&
-                    game_state.lines.contains(&l6) {num_boxes = num_boxes + 1;}  
+                    if game_state.line_set.contains(&l4) && 
+                    game_state.line_set.contains(&l5) &&
+                    game_state.line_set.contains(&l6) {boxes.push((self.x - 1, self.y));}
                 }
-                num_boxes
+                boxes
             }
         }
     }
 }
 
 impl GameState {
-    pub fn initial() -> Self {
+    pub fn initial(game: &Game) -> Self {
         // return an initial state of a game
         Self{
             moves: Vec::new(),
+            move_count: 0,
+            last_move: None,
             lines: Vec::new(),
             player1_boxes: 0,
             player2_boxes: 0,
             status: GameStatus::Ready,
-            next_player: 2, //usize b/c just going to be an indicator of the player - not the address
+            result: GameResult::None,
+            //usize b/c just going to be an indicator of the player - not the address.
+            //A coin flip starts with player 2 picking a seed.
+            next_player: match game.settings.first_player {
+                FirstPlayer::Creator => 1,
+                FirstPlayer::Opponent | FirstPlayer::Random => 2,
+            },
+            resigned_player: None,
+            draw_offered_by: None,
+            undo_requested_by: None,
+            coin_flip: match game.settings.first_player {
+                FirstPlayer::Random => Some(CoinFlipStage::AwaitingSeed),
+                _ => None,
+            },
+            box_owners: vec![vec![0; game.settings.height - 1]; game.settings.width - 1],
+            line_set: LineSet::new(game.settings.width, game.settings.height),
+            width: game.settings.width,
+            height: game.settings.height,
+            player1_time_ms: game.settings.time_control.as_ref().map(|time_control| time_control.initial_ms),
+            player2_time_ms: game.settings.time_control.as_ref().map(|time_control| time_control.initial_ms),
+            last_move_at: game.created_at,
+            clocks_before_lines: Vec::new(),
+            line_players: Vec::new(),
+        }
+    }
+
+    //Nothing is timed until player 2 accepts the game. The first move records when that was, so
+    //before it is checked or applied the clocks and the abandonment window are moved to start from there.
+    pub fn started_by(&self, next_move: &Move) -> Option<GameState> {
+        match &next_move.acceptance {
+            Some(acceptance) if self.move_count == 0 && self.last_move_at != acceptance.accepted_at => {
+                Some(GameState { last_move_at: acceptance.accepted_at, ..self.clone() })
+            },
+            _ => None,
+        }
+    }
+
+    //Time a player has left at a given moment. Only the clock of the player whose turn it is runs.
+    pub fn time_remaining(&self, player: usize, now: u64) -> Option<u64> {
+        let clock = if player == 1 { self.player1_time_ms } else { self.player2_time_ms }?;
+        if player == self.next_player {
+            Some(clock.saturating_sub(now.saturating_sub(self.last_move_at)))
+        } else {
+            Some(clock)
+        }
+    }
+
+    //Stop the clock of the player who just placed a line and give them their increment
+    fn clocks_after_move(&self, game: &Game, player: usize, now: u64) -> (Option<u64>, Option<u64>) {
+        let increment = game.settings.time_control.as_ref().map(|time_control| time_control.increment_ms).unwrap_or(0);
+        let remaining = self.time_remaining(player, now).map(|time| time + increment);
+        if player == 1 {
+            (remaining, self.player2_time_ms)
+        } else {
+            (self.player1_time_ms, remaining)
+        }
+    }
+
+    //The line bitset isn't serialized, so it has to be rebuilt when a state is loaded from an entry
+    pub fn rebuild_line_set(&mut self) {
+        let mut line_set = LineSet::new(self.width, self.height);
+        for line in self.lines.iter() {
+            line_set.insert(line);
+        }
+        self.line_set = line_set;
+    }
+
+    //Total number of boxes on the board. Once this many have been completed the game is over.
+    pub fn max_boxes(&self) -> usize {
+        (self.width - 1) * (self.height - 1)
+    }
+
+    //Whoever has completed the most boxes wins, or the fewest in a Misere game. Only meaningful
+    //once every box is complete.
+    fn result_from_boxes(variant: &RuleVariant, player1_boxes: usize, player2_boxes: usize) -> GameResult {
+        let (player1_boxes, player2_boxes) = match variant {
+            RuleVariant::Standard => (player1_boxes, player2_boxes),
+            RuleVariant::Misere => (player2_boxes, player1_boxes),
+        };
+        if player1_boxes > player2_boxes {
+            GameResult::Player1Wins
+        } else if player2_boxes > player1_boxes {
+            GameResult::Player2Wins
+        } else {
+            GameResult::Draw
         }
     }
 
@@ -187,18 +410,19 @@ impl GameState {
         //Put a newline in the string we will eventually return to initialize it
         let mut disp = "\n".to_string();
 
-        let mut board = [[EMPTY_SPACE; (BOARD_SIZE * 2)]; (BOARD_SIZE * 2)];
+        //The board is indexed [x][y] so the outer dimension follows the width
+        let mut board = vec![vec![EMPTY_SPACE; self.height * 2]; self.width * 2];
         //Add identifying numbers to the grid
         //NOTE: These ranges start inclusive at the beginning and exclusive at the end!
-        for y in 0..BOARD_SIZE {
+        for y in 0..self.height {
             board[0][(2*y)+1] = digit_to_char(&y);
         }
-        for x in 0..BOARD_SIZE {
+        for x in 0..self.width {
             board[(2*x)+1][0] = digit_to_char(&x);
         }
         //Populate the grid w/dots on the vertices
-        for x in 0..BOARD_SIZE {
-            for y in 0..BOARD_SIZE {
+        for x in 0..self.width {
+            for y in 0..self.height {
                 board[(2*x)+1][(2*y)+1] = GRID_DOT;
             }
         }
@@ -214,9 +438,18 @@ impl GameState {
             }
         }
 
+        //Mark completed boxes with the number of the player who owns them
+        for (x, column) in self.box_owners.iter().enumerate() {
+            for (y, owner) in column.iter().enumerate() {
+                if *owner > 0 {
+                    board[(x+1)*2][(y+1)*2] = digit_to_char(owner);
+                }
+            }
+        }
+
         //Format the final string
-        for y in (0..(BOARD_SIZE * 2)).rev() {
-            for x in 0..(BOARD_SIZE * 2) {
+        for y in (0..(self.height * 2)).rev() {
+            for x in 0..(self.width * 2) {
                 //disp.push_str(&format!("x={},y={}", x, y));
                 disp.push_str(&format!("{}", board[x][y]));
             }
@@ -231,20 +464,82 @@ impl GameState {
                 GameStatus::Ready => {"Ready"},
                 GameStatus::InProgress => {"InProgress"},
                 GameStatus::Surrendered => {"Surrendered"},
-                GameStatus::Completed => {"Completed"},} ));
-        disp.push_str(&format!("Next Player's Turn:{}\n", self.next_player));
+                GameStatus::Completed => {"Completed"},
+                GameStatus::TimedOut => {"TimedOut"},
+                GameStatus::Abandoned => {"Abandoned"},
+                GameStatus::DrawAgreed => {"DrawAgreed"},} ));
+        disp.push_str(&format!("Result:{}\n",
+            match self.result {
+                GameResult::None => {"Undecided"},
+                GameResult::Player1Wins => {"Player 1 wins"},
+                GameResult::Player2Wins => {"Player 2 wins"},
+                GameResult::Draw => {"Draw"},} ));
+        match self.resigned_player {
+            Some(player) => disp.push_str(&format!("Player {} resigned\n", player)),
+            None => disp.push_str(&format!("Next Player's Turn:{}\n", self.next_player)),
+        }
+        if let Some(player) = self.draw_offered_by {
+            disp.push_str(&format!("Player {} has offered a draw\n", player));
+        }
+        match self.coin_flip {
+            Some(CoinFlipStage::AwaitingSeed) => disp.push_str("Waiting for player 2 to seed the coin flip\n"),
+            Some(CoinFlipStage::AwaitingReveal{..}) => disp.push_str("Waiting for player 1 to reveal the coin flip\n"),
+            None => {},
+        }
+        if let Some(player) = self.undo_requested_by {
+            disp.push_str(&format!("Player {} has asked to take back the last line\n", player));
+        }
+        //Clocks are shown as they stood after the last move
+        if let (Some(player1_time), Some(player2_time)) = (self.player1_time_ms, self.player2_time_ms) {
+            disp.push_str(&format!("Player 1 clock:{}\n", format_clock(player1_time)));
+            disp.push_str(&format!("Player 2 clock:{}\n", format_clock(player2_time)));
+        }
             
         disp.to_string()
     }
 
+    //Take back the most recently placed line that is still on the board. Any boxes it completed are
+    //given back, the clocks are put back as they were and it becomes the turn of the player who placed it again.
+    fn undo_last_line(&self) -> GameState {
+        let mut state = self.clone();
+        let line = match state.lines.pop() {
+            Some(line) => line,
+            None => return state,
+        };
+        //The other sides of any completed box next to the line were all there before it, so those are
+        //exactly the boxes this line completed
+        for (box_x, box_y) in line.completed_boxes(self) {
+            match state.box_owners[box_x][box_y] {
+                1 => state.player1_boxes -= 1,
+                2 => state.player2_boxes -= 1,
+                _ => {},
+            }
+            state.box_owners[box_x][box_y] = 0;
+        }
+        state.line_set.remove(&line);
+        //The increment for placing the line isn't kept. Time since then isn't charged to anyone, the
+        //clock starts again from the approval.
+        if let Some(clocks) = state.clocks_before_lines.pop() {
+            state.player1_time_ms = clocks.player1_time_ms;
+            state.player2_time_ms = clocks.player2_time_ms;
+        }
+        if let Some(player) = state.line_players.pop() {
+            state.next_player = player;
+        }
+        state
+    }
+
     pub fn evolve(&self, game: Game, next_move: &Move) -> GameState {
         // <<DEVCAMP>>
         // given a current state, a game and a move, compute the next state
         // You can assume all moves are valid
+        if let Some(started) = self.started_by(next_move) {
+            return started.evolve(game, next_move)
+        }
 
         /*  1) If no moves recorded and 2 players, state is ready
         *   2) If a player has resigned, state is surrendered
-        *   3) If all boxes completed, state is Completed. Boxes = (N-1)^2
+        *   3) If all boxes completed, state is Completed. Boxes = (width-1)*(height-1)
         *   4) Otherwise it is In Progress: Check if new line would complete a box
         *   If new box then increment player's by 1 or 2 accordingly and flag next turn as theirs. 
         *   Otherwise, flag opposite player's turns as next. */
@@ -254,20 +549,30 @@ impl GameState {
         let mut lines = self.lines.clone();
         let mut player1_boxes = self.player1_boxes.clone();
         let mut player2_boxes = self.player2_boxes.clone();
+        let mut box_owners = self.box_owners.clone();
+        let mut line_set = self.line_set.clone();
+        let mut clocks_before_lines = self.clocks_before_lines.clone();
+        let mut line_players = self.line_players.clone();
         //Don't assign these values because I don't look at them before overriding.
         let status; //= self.status.clone();
+        let result;
         let next_player; // = self.next_player.clone();
 
         //Add the new move to the state
         moves.push(next_move.clone());
 
-        match next_move.clone().move_type {
+        let mut next_state = match next_move.clone().move_type {
             MoveType::Place{x, y, direction} => {
                 let next_line = Line {x, y, direction};
-                let num_boxes = next_line.is_part_of_box(self);
-                
+                let completed = next_line.completed_boxes(self);
+                let num_boxes = completed.len();
+
                 //Figure out which player made the move
-                if game.player_1 == next_move.author {
+                let player = if game.player_1 == next_move.author {1} else {2};
+                for (box_x, box_y) in completed {
+                    box_owners[box_x][box_y] = player;
+                }
+                if player == 1 {
                     if num_boxes > 0 {
                         player1_boxes = player1_boxes + num_boxes;
                         next_player = 1;
@@ -283,11 +588,20 @@ impl GameState {
                         next_player = 1;
                     }
                 }
+                let (player1_time_ms, player2_time_ms) = self.clocks_after_move(&game, player, next_move.timestamp);
+                clocks_before_lines.push(Clocks {
+                    player1_time_ms: self.player1_time_ms,
+                    player2_time_ms: self.player2_time_ms,
+                });
+                line_players.push(player);
+                line_set.insert(&next_line);
                 lines.push(next_line);
-                if player1_boxes + player2_boxes == MAX_BOXES {
+                if player1_boxes + player2_boxes == self.max_boxes() {
                     status = GameStatus::Completed;
+                    result = GameState::result_from_boxes(&game.settings.variant, player1_boxes, player2_boxes);
                 } else {
                     status = GameStatus::InProgress;
+                    result = GameResult::None;
                 }
 
                 //Finally return the new state
@@ -297,11 +611,142 @@ impl GameState {
                     player1_boxes,
                     player2_boxes,
                     status,
+                    result,
                     next_player,
+                    resigned_player: None,
+                    //Placing a line declines any draw the opponent offered, but not the player's own offer
+                    draw_offered_by: self.draw_offered_by.filter(|offered_by| *offered_by == player),
+                    //A new line changes what the last line is, so any pending undo request no longer applies
+                    undo_requested_by: None,
+                    coin_flip: None,
+                    box_owners,
+                    line_set,
+                    width: self.width,
+                    height: self.height,
+                    player1_time_ms,
+                    player2_time_ms,
+                    last_move_at: next_move.timestamp,
+                    clocks_before_lines,
+                    line_players,
+                    ..self.clone()
                 }
             }
-            //Add in MoveType of Resigned
-        }
+            MoveType::Resign => {
+                //The board is left as it is, the game just ends with the other player winning
+                let resigned_player = game.player_number(&next_move.author);
+                GameState {
+                    moves,
+                    status: GameStatus::Surrendered,
+                    result: match resigned_player {
+                        Some(1) => GameResult::Player2Wins,
+                        _ => GameResult::Player1Wins,
+                    },
+                    resigned_player,
+                    ..self.clone()
+                }
+            }
+            MoveType::ClaimTimeout => {
+                //Only the player waiting on their opponent can claim, so the flagged clock is the next player's
+                let (player1_time_ms, player2_time_ms) = if self.next_player == 1 {
+                    (Some(0), self.player2_time_ms)
+                } else {
+                    (self.player1_time_ms, Some(0))
+                };
+                GameState {
+                    moves,
+                    status: GameStatus::TimedOut,
+                    result: if self.next_player == 1 { GameResult::Player2Wins } else { GameResult::Player1Wins },
+                    player1_time_ms,
+                    player2_time_ms,
+                    last_move_at: next_move.timestamp,
+                    ..self.clone()
+                }
+            }
+            //Draw offers don't use up a turn so the board, clocks and next player are all left alone
+            MoveType::OfferDraw => {
+                GameState {
+                    moves,
+                    draw_offered_by: game.player_number(&next_move.author),
+                    ..self.clone()
+                }
+            }
+            MoveType::AcceptDraw => {
+                GameState {
+                    moves,
+                    status: GameStatus::DrawAgreed,
+                    result: GameResult::Draw,
+                    draw_offered_by: None,
+                    ..self.clone()
+                }
+            }
+            MoveType::DeclineDraw => {
+                GameState {
+                    moves,
+                    draw_offered_by: None,
+                    ..self.clone()
+                }
+            }
+            MoveType::RequestUndo => {
+                GameState {
+                    moves,
+                    undo_requested_by: game.player_number(&next_move.author),
+                    ..self.clone()
+                }
+            }
+            MoveType::ApproveUndo => {
+                //The moves stay in the list for auditing, only the effects of the last line are rolled back
+                let mut state = self.undo_last_line();
+                state.moves = moves;
+                state.undo_requested_by = None;
+                state.last_move_at = next_move.timestamp;
+                state
+            }
+            //The coin flip moves happen before any line is placed and only decide who places the first one
+            MoveType::CoinFlipSeed{seed} => {
+                GameState {
+                    moves,
+                    next_player: 1,
+                    coin_flip: Some(CoinFlipStage::AwaitingReveal{seed}),
+                    last_move_at: next_move.timestamp,
+                    ..self.clone()
+                }
+            }
+            MoveType::CoinFlipReveal{secret} => {
+                let next_player = match &self.coin_flip {
+                    Some(CoinFlipStage::AwaitingReveal{seed}) => game::coin_flip_winner(&secret, seed),
+                    _ => self.next_player,
+                };
+                GameState {
+                    moves,
+                    next_player,
+                    coin_flip: None,
+                    last_move_at: next_move.timestamp,
+                    ..self.clone()
+                }
+            }
+            //Player 1 stalled the coin flip, so it is settled in player 2's favour
+            MoveType::ClaimCoinFlip => {
+                GameState {
+                    moves,
+                    next_player: 2,
+                    coin_flip: None,
+                    last_move_at: next_move.timestamp,
+                    ..self.clone()
+                }
+            }
+            MoveType::ClaimAbandonment => {
+                //Like a timeout, the claim comes from the waiting player so the next player is the one who left
+                GameState {
+                    moves,
+                    status: GameStatus::Abandoned,
+                    result: if self.next_player == 1 { GameResult::Player2Wins } else { GameResult::Player1Wins },
+                    ..self.clone()
+                }
+            }
+        };
+        next_state.move_count = self.move_count + 1;
+        next_state.last_move = Some(next_move.clone());
+        next_state
     }
 
 }
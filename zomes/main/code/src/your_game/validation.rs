@@ -1,8 +1,8 @@
 
-use crate::game::Game;
+use crate::game::{self, Game};
 use crate::game_move::Move;
 use crate::your_game::MoveType;
-use crate::your_game::state::Line; 
+use crate::your_game::state::{Line, GameStatus, CoinFlipStage};
 //This is an older reference, but is still in the API doc
 //use hdk::holochain_core_types::cas::content::Address;
 use hdk::{
@@ -31,49 +31,200 @@ use super::{
 impl Move {
 	pub fn is_valid(&self, game: Game, game_state: GameState) -> Result<(), String> {
         //Check if a move is valid given the current game and its state
-        is_players_turn(self.author.clone(), &game, &game_state)?; //"?" operator bails early if error
+        let game_state = game_state.started_by(self).unwrap_or(game_state);
+        is_player_in_game(&self.author, &game)?;
+        is_game_in_progress(&game_state)?;
+        is_after_previous_move(self.timestamp, &game, &game_state)?;
         match self.move_type.clone() {
             MoveType::Place{x, y, direction} => {
+                if game_state.coin_flip.is_some() {
+                    return Err("The coin flip deciding who goes first has not finished".into())
+                }
+                is_players_turn(self.author.clone(), &game, &game_state)?; //"?" operator bails early if error
+                has_time_left(self.timestamp, &game_state)?;
                 let pos = Line{x, y, direction};
-                pos.is_in_bounds()?;
+                pos.is_in_bounds(&game_state)?;
                 pos.is_empty(&game_state)?;
                 Ok(()) // if we made it this far success!
             }
+            MoveType::Resign => {
+                //Resigning doesn't have to wait for a turn
+                Ok(())
+            }
+            MoveType::ClaimTimeout => {
+                is_opponents_clock_expired(self.author.clone(), self.timestamp, &game, &game_state)
+            }
+            MoveType::ClaimAbandonment => {
+                has_opponent_abandoned(self.author.clone(), self.timestamp, &game, &game_state)
+            }
+            MoveType::OfferDraw => {
+                //Offers don't have to wait for a turn, but only one can be open at a time
+                match game_state.draw_offered_by {
+                    Some(_) => Err("A draw has already been offered".into()),
+                    None => Ok(()),
+                }
+            }
+            MoveType::AcceptDraw | MoveType::DeclineDraw => {
+                has_opponent_offered_draw(&self.author, &game, &game_state)
+            }
+            MoveType::RequestUndo => {
+                //Like draw offers, undo requests don't use up a turn
+                if game_state.lines.is_empty() {
+                    Err("There are no lines to take back".into())
+                } else if game_state.undo_requested_by.is_some() {
+                    Err("An undo has already been requested".into())
+                } else {
+                    Ok(())
+                }
+            }
+            MoveType::ApproveUndo => {
+                match game_state.undo_requested_by {
+                    Some(requested_by) if game.player_number(&self.author) != Some(requested_by) => Ok(()),
+                    Some(_) => Err("You can't approve your own undo request".into()),
+                    None => Err("There is no undo request to approve".into()),
+                }
+            }
+            MoveType::CoinFlipSeed{..} => {
+                match game_state.coin_flip {
+                    Some(CoinFlipStage::AwaitingSeed) if game.player_number(&self.author) == Some(2) => Ok(()),
+                    Some(CoinFlipStage::AwaitingSeed) => Err("Player 2 seeds the coin flip".into()),
+                    _ => Err("The coin flip is not waiting for a seed".into()),
+                }
+            }
+            MoveType::CoinFlipReveal{secret} => {
+                match game_state.coin_flip {
+                    Some(CoinFlipStage::AwaitingReveal{..}) if game.player_number(&self.author) == Some(1) => {
+                        //The secret has to be the one player 1 committed to before seeing the seed
+                        if game.coin_flip_commitment == Some(game::coin_flip_commitment(&secret)) {
+                            Ok(())
+                        } else {
+                            Err("The secret does not match the coin flip commitment in the game".into())
+                        }
+                    }
+                    Some(CoinFlipStage::AwaitingReveal{..}) => Err("Player 1 reveals the coin flip".into()),
+                    _ => Err("The coin flip is not waiting for a reveal".into()),
+                }
+            }
+            MoveType::ClaimCoinFlip => {
+                has_reveal_timed_out(self.author.clone(), self.timestamp, &game, &game_state)
+            }
         }
     }
 }
 
+//Helper to make sure that only the two agents playing the game can make moves in it
+fn is_player_in_game(player: &Address, game: &Game) -> Result<(), String> {
+    match game.player_number(player) {
+        Some(_) => Ok(()),
+        None => Err("Only the two players in this game can make moves".into()),
+    }
+}
+
+//Helper to keep time moving forward. A move can't be timestamped before the move it follows, or
+//before the game was created if it is the first move.
+fn is_after_previous_move(timestamp: u64, game: &Game, game_state: &GameState) -> Result<(), String> {
+    let previous = match &game_state.last_move {
+        Some(last_move) => last_move.timestamp,
+        None => game.created_at,
+    };
+    if timestamp < previous {
+        Err(format!("Move timestamp {} is before the previous move or game creation at {}", timestamp, previous))
+    } else {
+        Ok(())
+    }
+}
+
+//Helper to stop any further moves, including resigning, once a game has finished
+fn is_game_in_progress(game_state: &GameState) -> Result<(), String> {
+    match game_state.status {
+        GameStatus::Completed => Err("The game is over: all of the boxes have been completed".into()),
+        GameStatus::Surrendered => Err("The game is over: a player has resigned".into()),
+        GameStatus::TimedOut => Err("The game is over: a player ran out of time".into()),
+        GameStatus::Abandoned => Err("The game is over: a player abandoned it".into()),
+        GameStatus::DrawAgreed => Err("The game is over: the players agreed to a draw".into()),
+        _ => Ok(()),
+    }
+}
+
 //Another helper for checking if it is the player's turn. Players take turns except for when
 //1 or 2 boxes is completed by a line and then that player gets to go again.
 fn is_players_turn(player: Address, game: &Game, game_state: &GameState) -> Result<(), String> {
-	let moves = &game_state.moves;
-    //Returns 0 if no moves or 1 if more
-    match moves.last() {
-        Some(last_move) => {
-            match last_move.move_type.clone() {
-                MoveType::Place{x, y, direction} => {
-                    let next_line = Line {x, y, direction};
-                    if next_line.is_part_of_box(game_state) > 0 {
-                        if last_move.author != player {
-                            Err("Other player completed 1-2 boxes and gets another turn.".into())
-                        } else {
-                            Ok(())
-                        }
-                    } else if last_move.author == player {
-                        Err("it is not this players turn".into())
-                    } else {
-                        Ok(())
-                    }
-                }
-            }
+    //evolve works out whose turn is next, including the extra turn after completing a box
+    if game.player_number(&player) == Some(game_state.next_player) {
+        return Ok(())
+    }
+    if game_state.lines.is_empty() {
+        //The game settings, or a coin flip, decide who goes first
+        return Err(format!("Player {} must make the first move", game_state.next_player))
+    }
+    match &game_state.last_move {
+        Some(last_move) if last_move.author != player => {
+            Err("Other player completed 1-2 boxes and gets another turn.".into())
         },
-        None => { //also need to handle the case where no moves have been made yet
-            if game.player_2 == player {
-                Ok(()) // Player 2 can go first by convention 
-            }
-            else {
-                Err("Player 2 must make the first move".into())
+        _ => Err("it is not this players turn".into()),
+    }
+}
+
+//Helper for answering a draw offer. Players can only accept or decline an offer their opponent made.
+fn has_opponent_offered_draw(player: &Address, game: &Game, game_state: &GameState) -> Result<(), String> {
+    match game_state.draw_offered_by {
+        Some(offered_by) if game.player_number(player) != Some(offered_by) => Ok(()),
+        Some(_) => Err("You can't answer your own draw offer".into()),
+        None => Err("There is no draw offer to answer".into()),
+    }
+}
+
+//Helper for timed games. A line can't be placed once the player's clock has run out, all they
+//can do then is wait for their opponent to claim the win.
+fn has_time_left(timestamp: u64, game_state: &GameState) -> Result<(), String> {
+    match game_state.time_remaining(game_state.next_player, timestamp) {
+        Some(0) => Err("Your clock has run out".into()),
+        _ => Ok(()),
+    }
+}
+
+//Helper for claiming a win on time. The claim is only valid when it is the opponent's turn and
+//their clock has run out by the time the claim was made.
+fn is_opponents_clock_expired(player: Address, timestamp: u64, game: &Game, game_state: &GameState) -> Result<(), String> {
+    if game.settings.time_control.is_none() {
+        return Err("This game is not timed".into())
+    }
+    if game.player_number(&player) == Some(game_state.next_player) {
+        return Err("It is your turn, only the player waiting can claim a timeout".into())
+    }
+    match game_state.time_remaining(game_state.next_player, timestamp) {
+        Some(0) => Ok(()),
+        _ => Err("Opponent still has time left on their clock".into()),
+    }
+}
+
+//Helper for closing a stalled game. The claim is only valid when it is the opponent's turn and it
+//has been their turn for the inactivity window. Moves that don't end a turn, like offering a draw,
+//don't count as activity, otherwise a stalling player could keep pushing the window back.
+fn has_opponent_abandoned(player: Address, timestamp: u64, game: &Game, game_state: &GameState) -> Result<(), String> {
+    if game.player_number(&player) == Some(game_state.next_player) {
+        return Err("It is your turn, only the player waiting can claim the game was abandoned".into())
+    }
+    if timestamp.saturating_sub(game_state.last_move_at) >= game.abandon_after() {
+        Ok(())
+    } else {
+        Err(format!("The game can only be claimed as abandoned after {} ms without a move", game.abandon_after()))
+    }
+}
+
+//Helper for when player 1 won't reveal the coin flip after seeing the seed. Player 2 can take the
+//first move once player 1 has had the game's reveal window since the seed to reveal it.
+fn has_reveal_timed_out(player: Address, timestamp: u64, game: &Game, game_state: &GameState) -> Result<(), String> {
+    match game_state.coin_flip {
+        Some(CoinFlipStage::AwaitingReveal{..}) if game.player_number(&player) == Some(2) => {
+            //The seed was the last move to change last_move_at, so this is the time since the seed
+            if timestamp.saturating_sub(game_state.last_move_at) >= game.coin_flip_reveal_within() {
+                Ok(())
+            } else {
+                Err(format!("Player 1 has {} ms after the seed to reveal the coin flip", game.coin_flip_reveal_within()))
             }
         }
+        Some(CoinFlipStage::AwaitingReveal{..}) => Err("Only player 2 can claim the coin flip".into()),
+        _ => Err("The coin flip is not waiting for a reveal".into()),
     }
-}
\ No newline at end of file
+}